@@ -1,4 +1,19 @@
 pub mod file_io;
 pub mod timer;
 pub mod memory;
-pub mod display;
\ No newline at end of file
+pub mod display;
+pub mod keyboard_io;
+pub mod movie;
+pub mod frontend;
+pub mod speed;
+pub mod terminal;
+pub mod image;
+pub mod filter;
+pub mod palette;
+pub mod phosphor;
+pub mod capture;
+pub mod export;
+pub mod database;
+pub mod container;
+pub mod hexfile;
+pub mod octo;
\ No newline at end of file
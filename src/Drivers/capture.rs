@@ -0,0 +1,172 @@
+/*
+ *  ===========================================================
+ *
+ *     Filename:    capture.rs
+ *  Description:    record the framebuffer as animated GIF or
+ *                  APNG at the emulated 60Hz
+ *
+ *  ===========================================================
+ * */
+
+extern crate gif;
+extern crate png;
+
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+use std::path::Path;
+
+use super::display::{HORIZONTAL, VERTICAL};
+use super::filter::Filter;
+use super::image::Image;
+use super::palette::Palette;
+use super::phosphor::Shades;
+
+/**
+ *  Container of a capture, chosen by the file extension
+ *  - Gif       .gif, delays in 1/100s, rounded so the total stays locked to 60Hz
+ *  - Apng      .png or .apng, exact delays of n/60s
+ * */
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Gif,
+    Apng
+}
+
+/**
+ *  Collects the frames of a recording, identical consecutive frames are merged
+ *  into one longer frame
+ * */
+pub struct Capture {
+    file: io::BufWriter<fs::File>,
+    format: Format,
+    scale: usize,
+    palette: Palette,
+    filter: Filter,
+
+    // distinct frames and the number of emulated frames each was shown for
+    frames: Vec<(Shades, u64)>
+}
+
+impl Capture {
+    /**
+     *  @func   is_capture()    check if a filename names a GIF or APNG capture
+     *
+     *  @param  filename        file given on the commandline
+     * */
+    pub fn is_capture(filename: &str) -> bool {
+        Capture::format(filename).is_some()
+    }
+
+    fn format(filename: &str) -> Option<Format> {
+        match Path::new(filename).extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "gif"           => Some(Format::Gif),
+            "png" | "apng"  => Some(Format::Apng),
+            _               => None,
+        }
+    }
+
+    /**
+     *  @func   create      create the capture file
+     *
+     *  @param  filename    .gif, .png or .apng file to be written
+     *
+     *  @param  scale       integer scale of the frames
+     *
+     *  @param  palette     colors of the pixel values
+     *
+     *  @param  filter      filter the frames are upscaled with, captures are RGB if it leaves the palette
+     * */
+    pub fn create(filename: &str, scale: usize, palette: Palette, filter: Filter) -> Result<Capture, io::Error> {
+        let format = Capture::format(filename)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Captures are written as .gif, .png or .apng"))?;
+
+        Ok(Capture {
+            file: io::BufWriter::new(fs::File::create(filename)?),
+            format,
+            scale,
+            palette,
+            filter,
+            frames: Vec::new()
+        })
+    }
+
+    /**
+     *  @func   frame       add the framebuffer after an emulated frame
+     *
+     *  @param  shades      shades shown for the next 1/60s
+     * */
+    pub fn frame(&mut self, shades: &Shades) {
+        match self.frames.last_mut() {
+            Some((last, duration)) if last == shades => *duration += 1,
+            _ => self.frames.push((*shades, 1)),
+        }
+    }
+
+    /**
+     *  @func   finish      encode the collected frames and write the file
+     * */
+    pub fn finish(self) -> Result<(), io::Error> {
+        if self.frames.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Capture ended before the first frame"));
+        }
+
+        match self.format {
+            Format::Gif     => self.write_gif(),
+            Format::Apng    => self.write_apng(),
+        }
+    }
+
+    fn write_gif(self) -> Result<(), io::Error> {
+        let width = (HORIZONTAL * self.scale) as u16;
+        let height = (VERTICAL * self.scale) as u16;
+
+        // RGB frames bring their own quantized palettes
+        let palette = if self.filter.indexed() { self.palette.shades() } else { Vec::new() };
+        let mut encoder = gif::Encoder::new(self.file, width, height, &palette).map_err(io::Error::other)?;
+        encoder.set_repeat(gif::Repeat::Infinite).map_err(io::Error::other)?;
+
+        // 1/100s since the start of the recording after frame n
+        let centis = |frame: u64| (frame * 100 + 30) / 60;
+
+        let mut start = 0;
+        for (shades, duration) in self.frames.iter() {
+            let mut frame = if self.filter.indexed() {
+                gif::Frame::from_indexed_pixels(width, height, self.filter.upscale(shades, self.scale), None)
+            } else {
+                gif::Frame::from_rgb_speed(width, height, &Image::from_shades(shades, self.scale, &self.palette, self.filter).rgb, 10)
+            };
+            frame.delay = (centis(start + duration) - centis(start)).min(u16::MAX as u64) as u16;
+            encoder.write_frame(&frame).map_err(io::Error::other)?;
+
+            start += duration;
+        }
+
+        // writes the trailer
+        encoder.into_inner()?.flush()
+    }
+
+    fn write_apng(self) -> Result<(), io::Error> {
+        let mut encoder = png::Encoder::new(self.file, (HORIZONTAL * self.scale) as u32, (VERTICAL * self.scale) as u32);
+        encoder.set_depth(png::BitDepth::Eight);
+        if self.filter.indexed() {
+            encoder.set_color(png::ColorType::Indexed);
+            encoder.set_palette(self.palette.shades());
+        } else {
+            encoder.set_color(png::ColorType::Rgb);
+        }
+        encoder.set_animated(self.frames.len() as u32, 0).map_err(io::Error::other)?;
+
+        let mut writer = encoder.write_header().map_err(io::Error::other)?;
+        for (shades, duration) in self.frames.iter() {
+            writer.set_frame_delay((*duration).min(u16::MAX as u64) as u16, 60).map_err(io::Error::other)?;
+            let data = if self.filter.indexed() {
+                self.filter.upscale(shades, self.scale)
+            } else {
+                Image::from_shades(shades, self.scale, &self.palette, self.filter).rgb
+            };
+            writer.write_image_data(&data).map_err(io::Error::other)?;
+        }
+        writer.finish().map_err(io::Error::other)
+    }
+}
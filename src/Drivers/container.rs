@@ -0,0 +1,532 @@
+/*
+ *  ===========================================================
+ *
+ *     Filename:    container.rs
+ *  Description:    unpack roms shipped as gzip, zip, hex text
+ *                  or Octo cartridge, detected by their content
+ *
+ *  ===========================================================
+ *
+ *  Containers may be nested, e.g. a hex listing in a zip archive. Everything that
+ *  isn't recognized is taken as the raw rom image. Intel HEX and S-record memory
+ *  images (see hexfile.rs) bring their own address and end the unpacking.
+ *
+ *  Hex text:       bytes as pairs of hex digits, optionally prefixed by 0x or $, separated
+ *                  by whitespace or commas, e.g. "00E0 A22A 600C". A leading "0200:" address
+ *                  of a line is skipped, comments start with #, ; or //. Text without any
+ *                  whitespace or 0x/$ prefix is left alone, it may just as well be a raw rom
+ *                  whose bytes happen to be hex digits, e.g. 6162 6364.
+ *
+ *  Octo cartridge: a GIF whose first frame holds the payload in the lowest two bits of
+ *                  its pixels, most significant bits first. The payload is a 32 bit big
+ *                  endian length followed by JSON: { "program": "...", "options": { ... } }.
+ *                  The program is Octo source, it is assembled by octo.rs.
+ * */
+
+extern crate flate2;
+extern crate gif;
+extern crate serde_json;
+
+use flate2::read::{DeflateDecoder, GzDecoder};
+use serde_json::Value;
+use std::io;
+use std::io::prelude::*;
+
+use super::hexfile::{self, HexFormat};
+use super::octo;
+
+// largest image that is unpacked, all of XO-CHIP's memory
+const MAX_SIZE: usize = 0x10000;
+
+// containers within containers at most
+const MAX_DEPTH: usize = 4;
+
+// extensions of roms, preferred over other files of a zip archive
+const ROM_EXTENSIONS: [&str; 4] = [".ch8", ".c8", ".sc8", ".xo8"];
+
+/**
+ *  Formats a rom is stored in
+ *  - Raw       the image as it is loaded to memory
+ *  - Gzip      gzip compressed
+ *  - Zip       first rom of a zip archive, stored or deflated
+ *  - Hex       hex text listing
+ *  - Cartridge Octo cartridge GIF
+ *  - Memory    Intel HEX or S-record memory image
+ * */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Raw,
+    Gzip,
+    Zip,
+    Hex,
+    Cartridge,
+    Memory(HexFormat)
+}
+
+impl Format {
+    /**
+     *  @func   detect()    format of a file by its content
+     *
+     *  @param  bytes       content of the file
+     * */
+    pub fn detect(bytes: &[u8]) -> Format {
+        if bytes.starts_with(&[0x1F, 0x8B, 0x08]) {
+            Format::Gzip
+        } else if bytes.starts_with(b"PK\x03\x04") {
+            Format::Zip
+        } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+            Format::Cartridge
+        } else if let Some(format) = HexFormat::detect(bytes).filter(|_| hexfile::parse(bytes).is_ok()) {
+            // raw roms may start like a record too, e.g. 3A41 (SNE VA,0x41)
+            Format::Memory(format)
+        } else if is_hex_text(bytes) {
+            Format::Hex
+        } else {
+            Format::Raw
+        }
+    }
+
+    /**
+     *  @func   name()      name shown to the user
+     * */
+    pub fn name(self) -> &'static str {
+        match self {
+            Format::Raw         => "raw",
+            Format::Gzip        => "gzip",
+            Format::Zip         => "zip",
+            Format::Hex         => "hex text",
+            Format::Cartridge   => "Octo cartridge",
+            Format::Memory(format) => format.name(),
+        }
+    }
+}
+
+/**
+ *  A rom image unpacked from its containers
+ * */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rom {
+    pub image: Vec<u8>,
+
+    // containers from the outermost in, empty for a raw image
+    pub formats: Vec<Format>,
+
+    // address of the image in memory if the container gives one, otherwise it goes to the entry point
+    pub base: Option<usize>,
+
+    // settings embedded in the containers, as commandline options
+    pub options: Vec<String>
+}
+
+/**
+ *  @func   unpack()    unpack a rom image from whatever containers it is stored in
+ *
+ *  @param  bytes       content of the rom file
+ * */
+pub fn unpack(bytes: Vec<u8>) -> Result<Rom, io::Error> {
+    let mut rom = Rom {
+        image: bytes,
+        formats: Vec::new(),
+        base: None,
+        options: Vec::new()
+    };
+
+    loop {
+        let format = Format::detect(&rom.image);
+        if format == Format::Raw {
+            return Ok(rom);
+        }
+        if rom.formats.len() == MAX_DEPTH {
+            return Err(invalid("Rom is nested in too many containers"));
+        }
+
+        rom.image = match format {
+            Format::Gzip        => limited(GzDecoder::new(rom.image.as_slice()))?,
+            Format::Zip         => unzip(&rom.image)?,
+            Format::Hex         => parse_hex(&rom.image)?,
+            Format::Cartridge   => {
+                let (image, options) = cartridge(&rom.image)?;
+                rom.options.extend(options);
+                image
+            },
+            Format::Memory(_)   => {
+                let (base, image) = hexfile::parse(&rom.image)?;
+                rom.formats.push(format);
+                rom.base = Some(base);
+                rom.image = image;
+                return Ok(rom);
+            },
+            Format::Raw         => unreachable!(),
+        };
+        rom.formats.push(format);
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/**
+ *  @func   limited()   read a decompressed stream, but not more than fits into memory
+ * */
+fn limited<R: Read>(reader: R) -> Result<Vec<u8>, io::Error> {
+    let mut image = Vec::new();
+    reader.take(MAX_SIZE as u64 + 1).read_to_end(&mut image)?;
+
+    if image.len() > MAX_SIZE {
+        return Err(invalid("Unpacked rom is too large"));
+    }
+    Ok(image)
+}
+
+/**
+ *  @func   is_hex_text()   text of hex bytes that is marked as text by whitespace or a 0x/$ prefix
+ * */
+fn is_hex_text(bytes: &[u8]) -> bool {
+    let text = bytes.iter().all(|b| b.is_ascii_graphic() || b.is_ascii_whitespace());
+    let marked = bytes.iter().any(u8::is_ascii_whitespace) || bytes.windows(2).any(|pair| pair == b"0x") || bytes.contains(&b'$');
+
+    !bytes.is_empty() && text && marked && parse_hex(bytes).is_ok()
+}
+
+/**
+ *  @func   parse_hex() bytes of a hex text listing
+ * */
+fn parse_hex(text: &[u8]) -> Result<Vec<u8>, io::Error> {
+    let text = std::str::from_utf8(text).map_err(|_| invalid("Hex text isn't text"))?;
+    let mut image = Vec::new();
+
+    for line in text.lines() {
+        let line = line.split(['#', ';']).next().unwrap_or("");
+        let line = line.split("//").next().unwrap_or("");
+
+        for (n, word) in line.split(|c: char| c.is_whitespace() || c == ',').filter(|word| !word.is_empty()).enumerate() {
+            if n == 0 && word.ends_with(':') {
+                continue;
+            }
+
+            let digits = word.strip_prefix("0x").or_else(|| word.strip_prefix('$')).unwrap_or(word);
+            if digits.is_empty() || digits.len() % 2 != 0 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(invalid("Hex text holds something other than hex bytes"));
+            }
+
+            for pair in digits.as_bytes().chunks(2) {
+                let pair = std::str::from_utf8(pair).map_err(|_| invalid("Hex text isn't text"))?;
+                image.push(u8::from_str_radix(pair, 16).map_err(|_| invalid("Hex text holds something other than hex bytes"))?);
+            }
+        }
+    }
+
+    if image.is_empty() {
+        return Err(invalid("Hex text is empty"));
+    }
+    Ok(image)
+}
+
+/**
+ *  @func   unzip()     the rom of a zip archive, found through the central directory
+ * */
+fn unzip(zip: &[u8]) -> Result<Vec<u8>, io::Error> {
+    let corrupt = || invalid("Zip archive is corrupt");
+    let u16_at = |at: usize| zip.get(at..at + 2).map(|b| u16::from_le_bytes([b[0], b[1]]) as usize).ok_or_else(corrupt);
+    let u32_at = |at: usize| zip.get(at..at + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize).ok_or_else(corrupt);
+
+    // the end of central directory record is followed by a comment of up to 64 KiB
+    let end = (0..zip.len().saturating_sub(21)).rev()
+        .take(0x10000 + 22)
+        .find(|&at| zip[at..].starts_with(b"PK\x05\x06"))
+        .ok_or_else(corrupt)?;
+
+    // method, compressed size, size, name and local header of every file
+    let mut files = Vec::new();
+    let mut at = u32_at(end + 16)?;
+    for _ in 0..u16_at(end + 10)? {
+        if !zip.get(at..).is_some_and(|entry| entry.starts_with(b"PK\x01\x02")) {
+            return Err(corrupt());
+        }
+
+        let name_length = u16_at(at + 28)?;
+        let name = zip.get(at + 46..at + 46 + name_length).ok_or_else(corrupt)?;
+        let name = String::from_utf8_lossy(name).to_lowercase();
+        if !name.ends_with('/') {
+            files.push((u16_at(at + 10)?, u32_at(at + 20)?, u32_at(at + 24)?, name, u32_at(at + 42)?));
+        }
+        at += 46 + name_length + u16_at(at + 30)? + u16_at(at + 32)?;
+    }
+
+    let (method, compressed, size, _, local) = files.iter()
+        .find(|(_, _, _, name, _)| ROM_EXTENSIONS.iter().any(|extension| name.ends_with(extension)))
+        .or_else(|| files.first())
+        .ok_or_else(|| invalid("Zip archive holds no files"))?
+        .clone();
+
+    if size > MAX_SIZE {
+        return Err(invalid("Unpacked rom is too large"));
+    }
+    if !zip.get(local..).is_some_and(|header| header.starts_with(b"PK\x03\x04")) {
+        return Err(corrupt());
+    }
+    let start = local + 30 + u16_at(local + 26)? + u16_at(local + 28)?;
+    let data = zip.get(start..start + compressed).ok_or_else(corrupt)?;
+
+    match method {
+        0 => Ok(data.to_vec()),
+        8 => limited(DeflateDecoder::new(data)),
+        _ => Err(io::Error::new(io::ErrorKind::Unsupported, "Zip archive uses an unsupported compression method")),
+    }
+}
+
+/**
+ *  @func   cartridge() program and options of an Octo cartridge
+ * */
+fn cartridge(gif: &[u8]) -> Result<(Vec<u8>, Vec<String>), io::Error> {
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::Indexed);
+    let mut decoder = options.read_info(gif).map_err(|e| invalid(&e.to_string()))?;
+    let frame = decoder.read_next_frame()
+        .map_err(|e| invalid(&e.to_string()))?
+        .ok_or_else(|| invalid("Octo cartridge has no frames"))?;
+
+    let bytes: Vec<u8> = frame.buffer.chunks_exact(4)
+        .map(|pixels| pixels.iter().fold(0, |byte, pixel| byte << 2 | (pixel & 3)))
+        .collect();
+
+    let length = bytes.get(..4)
+        .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]) as usize)
+        .ok_or_else(|| invalid("Octo cartridge is too small"))?;
+    let json = bytes.get(4..4 + length).ok_or_else(|| invalid("Octo cartridge is truncated"))?;
+    let payload: Value = serde_json::from_slice(json).map_err(|_| invalid("Octo cartridge holds no program"))?;
+
+    let program = payload["program"].as_str().ok_or_else(|| invalid("Octo cartridge holds no program"))?;
+    Ok((octo::assemble(program)?, octo_options(&payload["options"])))
+}
+
+/**
+ *  @func   octo_options()  Octo's options as commandline options, those without an equivalent are left out
+ * */
+fn octo_options(octo: &Value) -> Vec<String> {
+    let mut options = Vec::new();
+
+    if let Some(tickrate) = octo["tickrate"].as_u64().filter(|tickrate| *tickrate > 0) {
+        options.extend(["--ipf".to_string(), tickrate.to_string()]);
+    }
+
+    // the memory Octo allows the program to fill tells the platform it was written for
+    if let Some(size) = octo["maxSize"].as_u64() {
+        let platform = match size {
+            0..=3232    => "vip",
+            3233..=3584 => "schip",
+            _           => "xochip",
+        };
+        options.extend(["--platform".to_string(), platform.to_string()]);
+    }
+
+    if octo["shiftQuirks"].as_bool() == Some(true) {
+        options.push("--shift-quirk".to_string());
+    }
+
+    let colors: Vec<&str> = ["backgroundColor", "fillColor", "fillColor2", "blendColor"].iter()
+        .map_while(|name| octo[name].as_str())
+        .collect();
+    if colors.len() >= 2 {
+        options.extend(["--colors".to_string(), colors.join(",")]);
+    }
+    options
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    // readme.txt, the directory games/ and games/pong.ch8 holding 00E0 1200, stored
+    const STORED: [u8; 316] = [
+        0x50, 0x4B, 0x03, 0x04, 0x14, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x21, 0x50, 0xAC, 0x2A,
+        0x93, 0xD8, 0x02, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x0A, 0x00, 0x00, 0x00, 0x72, 0x65,
+        0x61, 0x64, 0x6D, 0x65, 0x2E, 0x74, 0x78, 0x74, 0x68, 0x69, 0x50, 0x4B, 0x03, 0x04, 0x14, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x21, 0x50, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x06, 0x00, 0x00, 0x00, 0x67, 0x61, 0x6D, 0x65, 0x73, 0x2F, 0x50, 0x4B,
+        0x03, 0x04, 0x14, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x21, 0x50, 0x6F, 0x7F, 0x52, 0xF0,
+        0x04, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x0E, 0x00, 0x00, 0x00, 0x67, 0x61, 0x6D, 0x65,
+        0x73, 0x2F, 0x70, 0x6F, 0x6E, 0x67, 0x2E, 0x63, 0x68, 0x38, 0x00, 0xE0, 0x12, 0x00, 0x50, 0x4B,
+        0x01, 0x02, 0x14, 0x03, 0x14, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x21, 0x50, 0xAC, 0x2A,
+        0x93, 0xD8, 0x02, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x0A, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x80, 0x01, 0x00, 0x00, 0x00, 0x00, 0x72, 0x65, 0x61, 0x64,
+        0x6D, 0x65, 0x2E, 0x74, 0x78, 0x74, 0x50, 0x4B, 0x01, 0x02, 0x14, 0x03, 0x14, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x21, 0x50, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x06, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x80, 0x01,
+        0x2A, 0x00, 0x00, 0x00, 0x67, 0x61, 0x6D, 0x65, 0x73, 0x2F, 0x50, 0x4B, 0x01, 0x02, 0x14, 0x03,
+        0x14, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x21, 0x50, 0x6F, 0x7F, 0x52, 0xF0, 0x04, 0x00,
+        0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x0E, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x80, 0x01, 0x4E, 0x00, 0x00, 0x00, 0x67, 0x61, 0x6D, 0x65, 0x73, 0x2F, 0x70, 0x6F,
+        0x6E, 0x67, 0x2E, 0x63, 0x68, 0x38, 0x50, 0x4B, 0x05, 0x06, 0x00, 0x00, 0x00, 0x00, 0x03, 0x00,
+        0x03, 0x00, 0xA8, 0x00, 0x00, 0x00, 0x7E, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ];
+
+    // pong.ch8 holding 00E0 1200 four times, deflated
+    const DEFLATED: [u8; 122] = [
+        0x50, 0x4B, 0x03, 0x04, 0x14, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x21, 0x50, 0x4A, 0x51,
+        0x18, 0xFF, 0x08, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x70, 0x6F,
+        0x6E, 0x67, 0x2E, 0x63, 0x68, 0x38, 0x63, 0x78, 0x20, 0xC4, 0xC0, 0x80, 0x84, 0x01, 0x50, 0x4B,
+        0x01, 0x02, 0x14, 0x03, 0x14, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x21, 0x50, 0x4A, 0x51,
+        0x18, 0xFF, 0x08, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x80, 0x01, 0x00, 0x00, 0x00, 0x00, 0x70, 0x6F, 0x6E, 0x67,
+        0x2E, 0x63, 0x68, 0x38, 0x50, 0x4B, 0x05, 0x06, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x01, 0x00,
+        0x36, 0x00, 0x00, 0x00, 0x2E, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ];
+
+    #[test]
+    fn raw_roms_that_look_like_records() {
+        // SNE VA,0x41 starts like an Intel HEX record, SE V3,0x31 like an S-record
+        for image in [&[0x3A, 0x41, 0x12, 0x00][..], &b"S31\x12\x00"[..]] {
+            let rom = unpack(image.to_vec()).unwrap();
+            assert_eq!(rom.formats, []);
+            assert_eq!(rom.image, image);
+        }
+    }
+
+    #[test]
+    fn raw_roms_of_hex_digits() {
+        // V1 := 0x62, V3 := 0x64 is the text "abcd"
+        let rom = unpack(b"abcd".to_vec()).unwrap();
+        assert_eq!(rom.formats, []);
+        assert_eq!(rom.image, b"abcd");
+    }
+
+    #[test]
+    fn hex_text() {
+        for text in [&b"00E0 A22A"[..], b"00E0A22A\n", b"0x00,0xE0,0xA2,0x2A", b"0200: 00 E0 # clear\n0202: A2 2A\n"] {
+            let rom = unpack(text.to_vec()).unwrap();
+            assert_eq!(rom.formats, [Format::Hex]);
+            assert_eq!(rom.image, [0x00, 0xE0, 0xA2, 0x2A]);
+        }
+    }
+
+    #[test]
+    fn memory_image() {
+        let rom = unpack(b":0300300002337A1E\n:00000001FF\n".to_vec()).unwrap();
+        assert_eq!(rom.formats, [Format::Memory(HexFormat::Intel)]);
+        assert_eq!(rom.base, Some(0x30));
+        assert_eq!(rom.image, [0x02, 0x33, 0x7A]);
+    }
+
+    fn gzip(image: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(image).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    // payload in the lowest two bits of the pixels, as Octo writes it
+    fn gif_of(payload: &[u8]) -> Vec<u8> {
+        let mut pixels: Vec<u8> = payload.iter().flat_map(|byte| [byte >> 6, byte >> 4 & 3, byte >> 2 & 3, byte & 3]).collect();
+        pixels.resize(pixels.len().div_ceil(64) * 64, 0);
+        let height = (pixels.len() / 64) as u16;
+
+        let mut gif = Vec::new();
+        let mut encoder = gif::Encoder::new(&mut gif, 64, height, &[0; 12]).unwrap();
+        encoder.write_frame(&gif::Frame::from_indexed_pixels(64, height, pixels, None)).unwrap();
+        drop(encoder);
+        gif
+    }
+
+    fn cartridge_of(json: &str) -> Vec<u8> {
+        let mut payload = (json.len() as u32).to_be_bytes().to_vec();
+        payload.extend(json.as_bytes());
+        gif_of(&payload)
+    }
+
+    #[test]
+    fn zip_rom_is_preferred_over_other_files() {
+        let rom = unpack(STORED.to_vec()).unwrap();
+        assert_eq!(rom.formats, [Format::Zip]);
+        assert_eq!(rom.image, [0x00, 0xE0, 0x12, 0x00]);
+    }
+
+    #[test]
+    fn zip_deflated() {
+        let rom = unpack(DEFLATED.to_vec()).unwrap();
+        assert_eq!(rom.formats, [Format::Zip]);
+        assert_eq!(rom.image, [0x00, 0xE0, 0x12, 0x00].repeat(4));
+    }
+
+    #[test]
+    fn zip_truncated() {
+        let err = unpack(STORED[..STORED.len() - 10].to_vec()).unwrap_err();
+        assert_eq!(err.to_string(), "Zip archive is corrupt");
+    }
+
+    #[test]
+    fn zip_corrupt_central_directory() {
+        // the central directory offset of the end record points into the first file
+        let mut zip = STORED.to_vec();
+        let end = zip.len() - 22;
+        zip[end + 16] = 0x01;
+        assert_eq!(unpack(zip).unwrap_err().to_string(), "Zip archive is corrupt");
+
+        // the local header offset of the central directory entry points past the header
+        let mut zip = DEFLATED.to_vec();
+        zip[0x2E + 42] = 0x01;
+        assert_eq!(unpack(zip).unwrap_err().to_string(), "Zip archive is corrupt");
+    }
+
+    #[test]
+    fn gzip_size_limit() {
+        let rom = unpack(gzip(&vec![0x12; MAX_SIZE])).unwrap();
+        assert_eq!(rom.formats, [Format::Gzip]);
+        assert_eq!(rom.image.len(), MAX_SIZE);
+
+        let err = unpack(gzip(&vec![0x12; MAX_SIZE + 1])).unwrap_err();
+        assert_eq!(err.to_string(), "Unpacked rom is too large");
+    }
+
+    #[test]
+    fn gzip_truncated() {
+        let packed = gzip(&[0x00, 0xE0, 0x12, 0x00]);
+        assert!(unpack(packed[..packed.len() / 2].to_vec()).is_err());
+    }
+
+    #[test]
+    fn nesting_depth_limit() {
+        let mut image = vec![0x00, 0xE0, 0x12, 0x00];
+        for _ in 0..MAX_DEPTH {
+            image = gzip(&image);
+        }
+        assert_eq!(unpack(image.clone()).unwrap().formats, [Format::Gzip; MAX_DEPTH]);
+
+        let err = unpack(gzip(&image)).unwrap_err();
+        assert_eq!(err.to_string(), "Rom is nested in too many containers");
+    }
+
+    #[test]
+    fn octo_option_mapping() {
+        let octo: Value = serde_json::from_str(r##"{ "tickrate": 15, "maxSize": 3584, "shiftQuirks": true,
+            "backgroundColor": "#112233", "fillColor": "#FFAA00", "blendColor": "#0000FF" }"##).unwrap();
+        assert_eq!(octo_options(&octo), ["--ipf", "15", "--platform", "schip", "--shift-quirk", "--colors", "#112233,#FFAA00"]);
+
+        let octo: Value = serde_json::from_str(r#"{ "tickrate": 0, "maxSize": 3232, "shiftQuirks": false }"#).unwrap();
+        assert_eq!(octo_options(&octo), ["--platform", "vip"]);
+
+        let octo: Value = serde_json::from_str(r#"{ "maxSize": 65024 }"#).unwrap();
+        assert_eq!(octo_options(&octo), ["--platform", "xochip"]);
+    }
+
+    #[test]
+    fn octo_cartridge() {
+        let rom = unpack(cartridge_of(r#"{ "program": ": main clear loop again", "options": { "tickrate": 20 } }"#)).unwrap();
+        assert_eq!(rom.formats, [Format::Cartridge]);
+        assert_eq!(rom.image, [0x00, 0xE0, 0x12, 0x02]);
+        assert_eq!(rom.options, ["--ipf", "20"]);
+    }
+
+    #[test]
+    fn octo_cartridge_truncated() {
+        // the length says more than the frame holds
+        let mut payload = 1000u32.to_be_bytes().to_vec();
+        payload.extend(b"{}");
+        assert_eq!(unpack(gif_of(&payload)).unwrap_err().to_string(), "Octo cartridge is truncated");
+
+        let err = unpack(cartridge_of(r#"{ "options": {} }"#)).unwrap_err();
+        assert_eq!(err.to_string(), "Octo cartridge holds no program");
+    }
+}
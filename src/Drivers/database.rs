@@ -0,0 +1,215 @@
+/*
+ *  ===========================================================
+ *
+ *     Filename:    database.rs
+ *  Description:    look up roms by their SHA-1 in databases of
+ *                  the CHIP-8 community's programs.json format
+ *
+ *  ===========================================================
+ *
+ *  A database is a JSON array of programs, every program lists its rom images by SHA-1:
+ *
+ *      [{ "title": "Pong (1 player)",
+ *         "roms": { "607c4f7f...": { "platforms": ["originalChip8"], "tickrate": 10,
+ *                                    "keys": { "up": 1, "down": 4 },
+ *                                    "colors": { "pixels": ["#000000", "#ffffff"] } } } }]
+ *
+ *  Fields the emulator has no use for are ignored.
+ * */
+
+extern crate serde_json;
+extern crate sha1;
+
+use serde_json::Value;
+use sha1::{Digest, Sha1};
+use std::fs;
+use std::io;
+
+// the database shipped with the emulator, searched after the ones given on the commandline
+const BUNDLED: &str = include_str!("../roms/database.json");
+
+// platform ids of the database and the platform they run on here, the first one supported is taken
+// and brings its quirks along (see Platform::shift_lsb())
+const PLATFORMS: [(&str, &str); 8] = [
+    ("originalChip8",   "vip"),
+    ("hybridVIP",       "vip"),
+    ("modernChip8",     "vip"),
+    ("chip8x",          "vip"),
+    ("chip48",          "schip"),
+    ("superchip1",      "schip"),
+    ("superchip",       "schip"),
+    ("xochip",          "xochip"),
+];
+
+// keys of the database the host can press, see KeyMap
+const KEYS: [&str; 6] = ["up", "down", "left", "right", "a", "b"];
+
+/**
+ *  What a database knows about a rom image, as settings of the commandline
+ * */
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Entry {
+    pub title: String,
+    pub platform: Option<String>,
+    pub ipf: Option<u64>,
+    pub keys: Option<String>,
+    pub colors: Option<String>
+}
+
+impl Entry {
+    /**
+     *  @func   args()      the entry as commandline options, given before the user's own so they override it
+     * */
+    pub fn args(&self) -> Vec<String> {
+        let mut args = vec!["--title".to_string(), self.title.clone()];
+
+        if let Some(platform) = &self.platform {
+            args.extend(["--platform".to_string(), platform.clone()]);
+        }
+        if let Some(ipf) = self.ipf {
+            args.extend(["--ipf".to_string(), ipf.to_string()]);
+        }
+        if let Some(keys) = &self.keys {
+            args.extend(["--keys".to_string(), keys.clone()]);
+        }
+        if let Some(colors) = &self.colors {
+            args.extend(["--colors".to_string(), colors.clone()]);
+        }
+        args
+    }
+}
+
+/**
+ *  Rom images by SHA-1, the first entry of a hash wins
+ * */
+pub struct Database {
+    entries: Vec<(String, Entry)>
+}
+
+impl Database {
+    /**
+     *  @func   bundled()   the database shipped with the emulator
+     * */
+    pub fn bundled() -> Result<Database, io::Error> {
+        Ok(Database {
+            entries: parse(BUNDLED)?
+        })
+    }
+
+    /**
+     *  @func   load()      add a database of the user, searched before the ones loaded so far
+     *
+     *  @param  filename    JSON file in programs.json format
+     * */
+    pub fn load(&mut self, filename: &str) -> Result<(), io::Error> {
+        let mut entries = parse(&fs::read_to_string(filename)?)?;
+        entries.append(&mut self.entries);
+        self.entries = entries;
+        Ok(())
+    }
+
+    /**
+     *  @func   lookup()    entry of a rom image
+     *
+     *  @param  image       raw rom image
+     * */
+    pub fn lookup(&self, image: &[u8]) -> Option<&Entry> {
+        let hash = sha1(image);
+        self.entries.iter().find(|(sha1, _)| *sha1 == hash).map(|(_, entry)| entry)
+    }
+}
+
+/**
+ *  @func   sha1()      SHA-1 of a rom image in lower case hex, as the database lists it
+ * */
+pub fn sha1(image: &[u8]) -> String {
+    Sha1::digest(image).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/**
+ *  @func   parse()     entries of all rom images of a database
+ * */
+fn parse(json: &str) -> Result<Vec<(String, Entry)>, io::Error> {
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid rom database: {}", msg));
+
+    let programs: Value = serde_json::from_str(json).map_err(|e| invalid(&e.to_string()))?;
+    let programs = programs.as_array().ok_or_else(|| invalid("expected an array of programs"))?;
+
+    let mut entries = Vec::new();
+    for program in programs {
+        let title = program["title"].as_str().ok_or_else(|| invalid("program without a title"))?;
+        let roms = program["roms"].as_object().ok_or_else(|| invalid("program without roms"))?;
+
+        for (hash, rom) in roms {
+            entries.push((hash.to_lowercase(), entry(title, rom)));
+        }
+    }
+    Ok(entries)
+}
+
+/**
+ *  @func   entry()     settings of a single rom image, unsupported values are left out
+ * */
+fn entry(title: &str, rom: &Value) -> Entry {
+    let platform = rom["platforms"].as_array()
+        .and_then(|platforms| platforms.iter()
+            .filter_map(Value::as_str)
+            .find_map(|id| PLATFORMS.iter().find(|(name, _)| *name == id)))
+        .map(|(_, platform)| platform.to_string());
+
+    let keys: Vec<String> = KEYS.iter()
+        .filter_map(|name| rom["keys"][name].as_u64().filter(|key| *key < 16).map(|key| format!("{}={:x}", name, key)))
+        .collect();
+
+    // off, on and for XO-CHIP the colors of the second plane and of both planes
+    let colors: Vec<&str> = rom["colors"]["pixels"].as_array()
+        .map(|pixels| pixels.iter().filter_map(Value::as_str).take(4).collect())
+        .unwrap_or_default();
+
+    Entry {
+        title: title.to_string(),
+        platform,
+        ipf: rom["tickrate"].as_u64().filter(|ipf| *ipf > 0),
+        keys: Some(keys.join(",")).filter(|keys| !keys.is_empty()),
+        colors: Some(colors.join(",")).filter(|_| colors.len() >= 2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sha1_known_answer() {
+        assert_eq!(sha1(b"abc"), "a9993e364706816aba3e25717850c26c9cd0d89d");
+    }
+
+    #[test]
+    fn bundled_entry_by_sha1() {
+        let database = Database::bundled().unwrap();
+        let entry = database.lookup(include_bytes!("../roms/Pong.ch8")).unwrap();
+
+        assert_eq!(entry.title, "Pong (1 player)");
+        assert_eq!(entry.args(), ["--title", "Pong (1 player)", "--platform", "vip", "--ipf", "10", "--keys", "up=1,down=4"]);
+        assert!(database.lookup(b"not a rom").is_none());
+    }
+
+    #[test]
+    fn first_supported_platform_and_hash_case() {
+        let json = r##"[{ "title": "Test", "roms": { "A9993E364706816ABA3E25717850C26C9CD0D89D": {
+            "platforms": ["megachip8", "superchip", "xochip"], "tickrate": 0,
+            "colors": { "pixels": ["#000000", "#ffffff"] } } } }]"##;
+        let database = Database { entries: parse(json).unwrap() };
+        let entry = database.lookup(b"abc").unwrap();
+
+        assert_eq!(entry.platform.as_deref(), Some("schip"));
+        assert_eq!(entry.ipf, None);
+        assert_eq!(entry.colors.as_deref(), Some("#000000,#ffffff"));
+    }
+
+    #[test]
+    fn malformed_database() {
+        let err = parse(r#"[{ "roms": {} }]"#).unwrap_err();
+        assert_eq!(err.to_string(), "Invalid rom database: program without a title");
+    }
+}
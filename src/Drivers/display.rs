@@ -1,157 +1,347 @@
+extern crate glutin;
 extern crate glutin_window;
 extern crate graphics;
 extern crate opengl_graphics;
 extern crate piston;
 
+use glutin::window::Fullscreen;
 use glutin_window::GlutinWindow as Window;
-use opengl_graphics::{GlGraphics, OpenGL};
-use piston::event_loop::{EventSettings, Events};
-use piston::input::{RenderArgs, RenderEvent, UpdateArgs, UpdateEvent};
-use piston::window::WindowSettings;
+use opengl_graphics::{CreateTexture, Format, GlGraphics, OpenGL, Texture, TextureSettings, UpdateTexture};
+use piston::input::{Button, Key, PressEvent, ReleaseEvent, RenderArgs};
+use piston::window::{AdvancedWindow, Window as _, WindowSettings};
+use std::io;
+
+use super::capture::Capture;
+use super::filter::Filter;
+use super::frontend::Frontend;
+use super::image::Image;
+use super::keyboard_io::{KeyMap, Keypad};
+use super::palette::Palette;
+use super::phosphor::Shades;
+use super::speed::Speed;
 
 // screen size
-const HORIZONTAL: usize = 64;
-const VERTICAL: usize = 32;
+pub const HORIZONTAL: usize = 64;
+pub const VERTICAL: usize = 32;
+
+// size of a pixel in the window when it is opened
+const WINDOW_SCALE: usize = 10;
 
-// constant colors
-const WHITE: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
-const BLACK: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
+// color of the bars around the framebuffer
+const LETTERBOX: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
 
 // simplified Line and Framebuffer type
-type LBuffer = [u8; HORIZONTAL];
-type FBuffer = [LBuffer; VERTICAL];
-
-#[derive(Debug)]
-struct Pixel(u16, u16);
-
-// TODO: The thing below
-// export and store in main -> pass as mutable to run()
-// externally writeable framebuffer, which is read by update() 
-pub static mut framebuffer: Box<FBuffer> = Box::new([
-    [0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0],
-    [0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0],
-    [0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0],
-    [0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0],
-    [0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0],
-    [0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0],
-    [0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0],
-    [0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0],
-    [0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0],
-    [0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0],
-    [0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0],
-    [0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0],
-    [0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0],
-    [0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0],
-    [0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0],
-    [0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0],
-    [0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0],
-    [0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0],
-    [0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0],
-    [0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0],
-    [0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0],
-    [0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0],
-    [0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0],
-    [0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0],
-    [0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0],
-    [0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0],
-    [0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0],
-    [0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0],
-    [0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0],
-    [0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0],
-    [0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0],
-    [0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0]
-]);
+pub type LBuffer = [u8; HORIZONTAL];
+pub type FBuffer = [LBuffer; VERTICAL];
+
+/**
+ *  How the framebuffer fills the window, both keep square pixels and letterbox the rest
+ *  - Integer   largest whole number of window pixels per pixel, fits if the window is too small
+ *  - Fit       as large as the window allows
+ * */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Viewport {
+    Integer,
+    Fit
+}
+
+impl Viewport {
+    /**
+     *  @func   from_name()     viewport by its commandline name
+     *
+     *  @param  name            "integer" or "fit"
+     * */
+    pub fn from_name(name: &str) -> Result<Viewport, io::Error> {
+        match name {
+            "integer"   => Ok(Viewport::Integer),
+            "fit"       => Ok(Viewport::Fit),
+            _           => Err(io::Error::new(io::ErrorKind::InvalidInput, "Unknown viewport")),
+        }
+    }
+}
+
+/**
+ *  How the window shows the framebuffer
+ * */
+#[derive(Debug, Clone)]
+pub struct Screen {
+    // window title, the speed is appended to it
+    pub title: String,
+
+    // integer scale of screenshots and captures
+    pub scale: usize,
+
+    // colors the framebuffer is drawn with, F9 switches to the next theme
+    pub palette: Palette,
+
+    // how the framebuffer is scaled to the window, F11 toggles fullscreen
+    pub viewport: Viewport,
+
+    // filter the framebuffer is upscaled with on the CPU before it is shown
+    pub filter: Filter,
+
+    // keypad keys of the arrow keys, space and enter
+    pub keys: KeyMap
+}
 
 /**
  * Handles the Display of the framebuffer and passes keyboard events to keyboard_io
- * 
- * TODO: keyboard_io
  */
 pub struct Display {
+    window: Window,
     gl: GlGraphics,
-    current_fbuffer: FBuffer
+    // shades of the framebuffer as blended by the phosphor
+    current_fbuffer: FBuffer,
+    scale: usize,
+    palette: Palette,
+    viewport: Viewport,
+    filter: Filter,
+    keys: KeyMap,
+
+    // the framebuffer as one texture, texels x texels per pixel, and its RGBA staging buffer
+    texels: usize,
+    texture: Texture,
+    rgba: Vec<u8>,
+
+    // the texture is behind current_fbuffer or the palette
+    dirty: bool,
+
+    // recording toggled with F10
+    recording: Option<Capture>,
+
+    // title of the window and the speed shown after it
+    title: String,
+    status: String
 }
 
 impl Display {
 
     // TODO: Maybe run in seperate thread
 
-    pub fn run() {
+    /**
+     * @func    open        open the window
+     *
+     * @param   screen      title, scale, colors, viewport and keys of the window
+     *
+     * @param   shades      shades shown until the first frame was presented
+     */
+    pub fn open(screen: Screen, shades: &Shades) -> Result<Display, io::Error> {
         // OpenGL::V2_1
         let gl = OpenGL::V3_2;
 
-        let mut window: Window = WindowSettings::new("Canvas", [320, 640])
+        let size = [(HORIZONTAL * WINDOW_SCALE) as u32, (VERTICAL * WINDOW_SCALE) as u32];
+        let window: Window = WindowSettings::new(screen.title.clone(), size)
             .graphics_api(gl)
-            .vsync(true)
+            // the machine loop paces the frames, waiting for the vertical blank as well would make it stutter
+            .vsync(false)
+            .resizable(true)
             .exit_on_esc(true)
             .build()
-            .unwrap();
-        
-        let mut display = Display {
-            gl: GlGraphics::new(gl),
-            current_fbuffer: framebuffer
-        };
+            .map_err(|e| io::Error::other(e.to_string()))?;
 
-        let mut events = Events::new(EventSettings::new());
-        
-        while let Some(e) = events.next(&mut window) {
-            
-            if let Some(args) = e.render_args() {
-                display.render(&args);
-            }
+        // without a filter the GPU scales, a filtered framebuffer is uploaded at the scale it was filtered to
+        let texels = if screen.filter == Filter::Nearest { 1 } else { screen.scale };
+        let rgba = vec![0; HORIZONTAL * VERTICAL * texels * texels * 4];
+        let settings = TextureSettings::new().filter(opengl_graphics::Filter::Nearest);
+        let size = [(HORIZONTAL * texels) as u32, (VERTICAL * texels) as u32];
+        let texture = Texture::create(&mut (), Format::Rgba8, &rgba, size, &settings)
+            .map_err(io::Error::other)?;
 
-            if let Some(args) = e.update_args() {
-                display.update();
-            }
+        Ok(Display {
+            window,
+            gl: GlGraphics::new(gl),
+            current_fbuffer: *shades,
+            scale: screen.scale,
+            palette: screen.palette,
+            viewport: screen.viewport,
+            filter: screen.filter,
+            keys: screen.keys,
+            texels,
+            texture,
+            rgba,
+            dirty: true,
+            recording: None,
+            title: screen.title,
+            status: String::new()
+        })
+    }
+
+    /**
+     * @func    hotkey      handle the keys of the emulator itself
+     *
+     * @param   key         key that was pressed
+     *
+     * @param   speed       changed by P (pause), N (single frame), [ ] (speed) and - = (instructions per frame)
+     *
+     * @param   frame       frame about to be executed
+     */
+    fn hotkey(&mut self, key: Key, speed: &mut Speed, frame: u64) -> Result<(), io::Error> {
+        match key {
+            Key::P              => speed.pause(),
+            Key::N              => speed.advance(),
+            Key::LeftBracket    => speed.slower(),
+            Key::RightBracket   => speed.faster(),
+            Key::Minus          => speed.less_ipf(),
+            Key::Equals         => speed.more_ipf(),
+            Key::F12 => {
+                let filename = format!("screenshot-{:06}.png", frame);
+                Image::from_shades(&self.current_fbuffer, self.scale, &self.palette, self.filter).write_png(&filename)?;
+                println!("Screenshot: {}", filename);
+            },
+            Key::F11 => {
+                let ctx = self.window.ctx.window();
+                match ctx.fullscreen() {
+                    Some(_) => ctx.set_fullscreen(None),
+                    None    => ctx.set_fullscreen(Some(Fullscreen::Borderless(None))),
+                }
+            },
+            Key::F10 => match self.recording.take() {
+                Some(recording) => {
+                    recording.finish()?;
+                    println!("Recording stopped");
+                },
+                None => {
+                    let filename = format!("recording-{:06}.gif", frame);
+                    self.recording = Some(Capture::create(&filename, self.scale, self.palette, self.filter)?);
+                    println!("Recording: {}", filename);
+                },
+            },
+            Key::F9 => {
+                self.palette = self.palette.next();
+                self.dirty = true;
+                println!("Theme: {}", self.palette.name());
+            },
+            _ => {},
         }
+        Ok(())
     }
 
     /**
-     * @func    translate_framebuffer   translate framebuffer to Vector of drawable Pixel locations
-     * 
-     * @param   framebuffer             input framebuffer to be translated
+     * @func    upload      refresh the texture from the current framebuffer if it is dirty
      */
-    fn translate_framebuffer(self, fbuffer: FBuffer, length: u16) -> Vec<Pixel> {
-        let mut pixel_loc: Vec<Pixel> = Vec::new();
-
-        for y in 0..(VERTICAL-1) {
-            for x in 0..(HORIZONTAL-1) {
-                if fbuffer[y][x] == 1 {
-                    println!("{:?}", Pixel((x as u16)*length, (y as u16)*length));
-                    pixel_loc.push(Pixel((x as u16)*length,  (y as u16)*length));
-                } 
+    fn upload(&mut self) -> Result<(), io::Error> {
+        if !self.dirty {
+            return Ok(());
+        }
+
+        if self.filter == Filter::Nearest {
+            for (texel, shade) in self.rgba.chunks_exact_mut(4).zip(self.current_fbuffer.iter().flatten()) {
+                let rgb = self.palette.shade(*shade);
+                texel.copy_from_slice(&[rgb[0], rgb[1], rgb[2], 0xFF]);
+            }
+        } else {
+            let image = Image::from_shades(&self.current_fbuffer, self.texels, &self.palette, self.filter);
+            for (texel, rgb) in self.rgba.chunks_exact_mut(4).zip(image.rgb.chunks_exact(3)) {
+                texel.copy_from_slice(&[rgb[0], rgb[1], rgb[2], 0xFF]);
             }
         }
 
-        pixel_loc
+        let size = [(HORIZONTAL * self.texels) as u32, (VERTICAL * self.texels) as u32];
+        UpdateTexture::update(&mut self.texture, &mut (), Format::Rgba8, &self.rgba, [0, 0], size)
+            .map_err(io::Error::other)?;
+        self.dirty = false;
+        Ok(())
     }
 
     /**
      * @func    render      render framebuffer
      */
-    fn render(&mut self, args: &RenderArgs) {
-        use graphics::*;
-        
-        // pixel dimensions
-        // TODO: Derive pixel size from viewport
-        let pixel_dims = rectangle::square(0.0, 0.0, 10.0);
-
-        // draw the framebuffer on viewport
-        self.gl.draw(args.viewport(), |c, gl| {
-            // **Paint** background black
-            clear(BLACK, gl);
+    fn render(&mut self, args: &RenderArgs) -> Result<(), io::Error> {
+        use graphics::clear;
 
-            // iterate over pixel locations Vec and draw pixel
-            for pixel_loc in self.translate_framebuffer(self.current_fbuffer, 10) {
-                rectangle(WHITE, pixel_dims, c.transform.trans(pixel_loc.0 as f64, pixel_loc.1 as f64), gl);    
-            }
+        self.upload()?;
+
+        // pixel dimensions, derived from the window in its own coordinates
+        let (length, left, top) = self.layout(args.window_size);
+        let screen = [left, top, length * HORIZONTAL as f64, length * VERTICAL as f64];
+        let texture = &self.texture;
+
+        // the back buffer is undefined after a swap, so the letterbox and the single
+        // textured quad are drawn every time, only the upload depends on the dirty flag
+        self.gl.draw(args.viewport(), |c, gl| {
+            clear(LETTERBOX, gl);
+            graphics::Image::new().rect(screen).draw(texture, &c.draw_state, c.transform, gl);
         });
+        Ok(())
     }
 
     /**
-     * @func    update              called by OpenGL on update -> sets the current framebuffer that is rendered to the value read from framebuffer
+     * @func    layout      size of a pixel and the top left corner of the framebuffer in the window
+     *
+     * @param   window      width and height of the window
      */
-    fn update(&mut self) {
-        self.current_fbuffer = framebuffer;
+    fn layout(&self, window: [f64; 2]) -> (f64, f64, f64) {
+        let fit = (window[0] / HORIZONTAL as f64).min(window[1] / VERTICAL as f64);
+        let length = match self.viewport {
+            Viewport::Integer if fit >= 1.0 => fit.floor(),
+            _                               => fit,
+        };
+
+        let left = ((window[0] - length * HORIZONTAL as f64) / 2.0).floor();
+        let top = ((window[1] - length * VERTICAL as f64) / 2.0).floor();
+        (length, left, top)
     }
-}
\ No newline at end of file
+}
+
+/**
+ * Shows the machine until the window is closed
+ */
+impl Frontend for Display {
+    fn poll(&mut self, keypad: &mut Keypad, speed: &mut Speed, frame: u64) -> Result<bool, io::Error> {
+        // the machine loop waits for the frames, only take the input that arrived meanwhile
+        while let Some(e) = self.window.poll_event() {
+            if let Some(Button::Keyboard(key)) = e.press_args() {
+                self.hotkey(key, speed, frame)?;
+
+                if self.status != speed.status() {
+                    self.status = speed.status();
+                    self.window.set_title(format!("{} - {}", self.title, self.status));
+                }
+
+                if let Some(k) = self.keys.map_key(key) {
+                    keypad.press(k);
+                }
+            }
+
+            if let Some(Button::Keyboard(key)) = e.release_args() {
+                if let Some(k) = self.keys.map_key(key) {
+                    keypad.release(k);
+                }
+            }
+        }
+
+        if self.window.should_close() {
+            return Ok(false);
+        }
+
+        // once per host frame, only the last of the frames run since the last poll is shown
+        let args = RenderArgs {
+            ext_dt: 0.0,
+            window_size: self.window.size().into(),
+            draw_size: self.window.draw_size().into()
+        };
+        self.render(&args)?;
+        self.window.swap_buffers();
+        Ok(true)
+    }
+
+    // the window is redrawn on the next poll, the texture only if the shades changed
+    fn present(&mut self, shades: &Shades, _frame: u64) -> Result<(), io::Error> {
+        if let Some(recording) = &mut self.recording {
+            recording.frame(shades);
+        }
+
+        if self.current_fbuffer != *shades {
+            self.current_fbuffer = *shades;
+            self.dirty = true;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), io::Error> {
+        match self.recording.take() {
+            Some(recording) => recording.finish(),
+            None            => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,164 @@
+/*
+ *  ===========================================================
+ *
+ *     Filename:    export.rs
+ *  Description:    stream every emulated frame uncompressed as
+ *                  Y4M video or numbered PPM/PBM images
+ *
+ *  ===========================================================
+ * */
+
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+
+use super::display::{HORIZONTAL, VERTICAL};
+use super::filter::Filter;
+use super::frontend::Frontend;
+use super::image::Image;
+use super::keyboard_io::Keypad;
+use super::palette::Palette;
+use super::speed::Speed;
+use super::phosphor::Shades;
+
+/**
+ *  Target of an export, chosen by the file extension
+ *  - Y4M       one YUV4MPEG2 stream, 4:4:4 at 60 frames per second
+ *  - Ppm       <name>-<frame>.ppm, binary RGB (P6) per frame
+ *  - Pbm       <name>-<frame>.pbm, binary bitmap (P4) per frame, dark colors are set
+ * */
+enum Target {
+    Y4m(io::BufWriter<fs::File>),
+    Ppm(PathBuf),
+    Pbm(PathBuf)
+}
+
+/**
+ *  Image sequence frontend, writes each frame through Image, so the export matches
+ *  screenshots and captures
+ * */
+pub struct Export {
+    target: Target,
+    scale: usize,
+    palette: Palette,
+    filter: Filter
+}
+
+impl Export {
+    /**
+     *  @func   create      open an export
+     *
+     *  @param  filename    .y4m stream or .ppm/.pbm name the frame numbers are appended to
+     *
+     *  @param  scale       integer scale of the frames
+     *
+     *  @param  palette     colors of the pixel values
+     *
+     *  @param  filter      filter the frames are upscaled with
+     * */
+    pub fn create(filename: &str, scale: usize, palette: Palette, filter: Filter) -> Result<Export, io::Error> {
+        let path = Path::new(filename);
+        let extension = path.extension().and_then(|ext| ext.to_str()).map(|ext| ext.to_ascii_lowercase());
+
+        let target = match extension.as_deref() {
+            Some("y4m") => {
+                let mut writer = io::BufWriter::new(fs::File::create(path)?);
+                writeln!(writer, "YUV4MPEG2 W{} H{} F60:1 Ip A1:1 C444", HORIZONTAL * scale, VERTICAL * scale)?;
+                Target::Y4m(writer)
+            },
+            Some("ppm") => Target::Ppm(path.to_path_buf()),
+            Some("pbm") => Target::Pbm(path.to_path_buf()),
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Exports are written as .y4m, .ppm or .pbm")),
+        };
+
+        Ok(Export {
+            target,
+            scale,
+            palette,
+            filter
+        })
+    }
+}
+
+impl Frontend for Export {
+    // frames are exported as fast as they are emulated, nobody is at the keyboard
+    fn poll(&mut self, _keypad: &mut Keypad, _speed: &mut Speed, _frame: u64) -> Result<bool, io::Error> {
+        Ok(true)
+    }
+
+    fn present(&mut self, shades: &Shades, frame: u64) -> Result<(), io::Error> {
+        let image = Image::from_shades(shades, self.scale, &self.palette, self.filter);
+
+        match &mut self.target {
+            Target::Y4m(writer) => {
+                // BT.601 studio range, full planes in Y, Cb, Cr order
+                let ycbcr: Vec<[u8; 3]> = image.rgb.chunks(3).map(|rgb| to_ycbcr(rgb[0], rgb[1], rgb[2])).collect();
+                writeln!(writer, "FRAME")?;
+                for plane in 0..3 {
+                    let bytes: Vec<u8> = ycbcr.iter().map(|pixel| pixel[plane]).collect();
+                    writer.write_all(&bytes)?;
+                }
+                Ok(())
+            },
+            Target::Ppm(path) => {
+                let mut file = io::BufWriter::new(fs::File::create(numbered(path, frame))?);
+                write!(file, "P6\n{} {}\n255\n", image.width, image.height)?;
+                file.write_all(&image.rgb)?;
+                file.flush()
+            },
+            Target::Pbm(path) => {
+                let mut file = io::BufWriter::new(fs::File::create(numbered(path, frame))?);
+                write!(file, "P4\n{} {}\n", image.width, image.height)?;
+
+                // rows are padded to whole bytes, the most significant bit comes first
+                for row in image.rgb.chunks(image.width * 3) {
+                    let bits: Vec<u8> = row.chunks(3)
+                        .collect::<Vec<_>>()
+                        .chunks(8)
+                        .map(|pixels| pixels.iter().enumerate().fold(0, |byte, (n, rgb)| {
+                            if to_ycbcr(rgb[0], rgb[1], rgb[2])[0] < 0x80 { byte | (0x80 >> n) } else { byte }
+                        }))
+                        .collect();
+                    file.write_all(&bits)?;
+                }
+                file.flush()
+            },
+        }
+    }
+
+    fn paced(&self) -> bool {
+        false
+    }
+
+    // flushes the stream
+    fn finish(&mut self) -> Result<(), io::Error> {
+        match &mut self.target {
+            Target::Y4m(writer)     => writer.flush(),
+            _                       => Ok(()),
+        }
+    }
+}
+
+/**
+ *  @func   numbered()  file of a single frame, <name>-<frame>.<extension>
+ * */
+fn numbered(path: &Path, frame: u64) -> PathBuf {
+    let stem = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("frame");
+    let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+
+    path.with_file_name(format!("{}-{:06}.{}", stem, frame, extension))
+}
+
+/**
+ *  @func   to_ycbcr()  convert a color to BT.601 studio range YCbCr
+ * */
+fn to_ycbcr(r: u8, g: u8, b: u8) -> [u8; 3] {
+    let (r, g, b) = (r as f64, g as f64, b as f64);
+
+    let y = 16.0 + (65.481 * r + 128.553 * g + 24.966 * b) / 255.0;
+    let cb = 128.0 + (-37.797 * r - 74.203 * g + 112.0 * b) / 255.0;
+    let cr = 128.0 + (112.0 * r - 93.786 * g - 18.214 * b) / 255.0;
+
+    [y.round() as u8, cb.round() as u8, cr.round() as u8]
+}
@@ -1,38 +1,184 @@
-/* 
+/*
  *  ===========================================================
- *  
- *     Filename:    fileIO.rs
- *  Description:    read file functions to handle bytewise read
- *                  operations
+ *
+ *     Filename:    file_io.rs
+ *  Description:    load roms from files, stdin or memory and
+ *                  check they fit where they are loaded to
  *
  *  ===========================================================
+ *
+ *  Reading and unpacking (see container.rs) come first, the size is checked by place()
+ *  once the platform is known, as the rom database may choose it by the unpacked image.
  * */
 
-use std::io;
+use std::fmt;
 use std::fs;
+use std::io;
 use std::io::prelude::*;
+use std::path::Path;
 
-pub fn filesize(filename: &str) -> io::Result<u64>{
-    let meta = fs::metadata(filename)?;
+use super::container::{self, Rom};
+use super::memory::MEMSIZE;
+use super::super::error::Chip8Error;
+use super::super::Interpreter::platform::Platform;
 
-    if meta.len() == 0 {
-        Err(io::Error::new(
-                io::ErrorKind::Other, 
-                "Filesize zero"))
-    } else {
-        Ok(meta.len())
+// largest file read, containers such as hex text are larger than the image they hold
+const READ_LIMIT: usize = 0x100000;
+
+/**
+ *  Where a rom is read from
+ *  - Path      a file
+ *  - Stdin     standard input, "-" on the commandline
+ * */
+#[derive(Debug, Clone, Copy)]
+pub enum Source<'a> {
+    Path(&'a Path),
+    Stdin
+}
+
+impl<'a> Source<'a> {
+    /**
+     *  @func   from_arg()  source of a commandline argument
+     *
+     *  @param  arg         "-" for stdin, anything else is a path
+     * */
+    pub fn from_arg(arg: &'a str) -> Source<'a> {
+        match arg {
+            "-" => Source::Stdin,
+            _   => Source::Path(Path::new(arg)),
+        }
     }
 }
 
-pub fn read_binary(filename: &str) -> io::Result<Vec<u8>> {
-    let mut fhandler = fs::File::open(filename)?;
-    let mut fbuffer = Vec::new();
+impl fmt::Display for Source<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Source::Path(path)  => write!(f, "{}", path.display()),
+            Source::Stdin       => write!(f, "<stdin>"),
+        }
+    }
+}
 
-    //for byte in fhandler.bytes() {
-    //    println!("{}", byte.unwrap());
-    //}
-    
-    fhandler.read_to_end(&mut fbuffer);
+/**
+ *  @func   read()      all bytes of a source, as they are
+ *
+ *  @param  source      file or stdin
+ *
+ *  The file is read once, its size is what was read, not what the file system said before.
+ * */
+pub fn read(source: Source) -> Result<Vec<u8>, Chip8Error> {
+    let unreadable = |err: io::Error| Chip8Error::RomUnreadable { name: source.to_string(), err };
+    let reader: Box<dyn Read> = match source {
+        Source::Path(path)  => Box::new(fs::File::open(path).map_err(unreadable)?),
+        Source::Stdin       => Box::new(io::stdin().lock()),
+    };
+
+    // one byte more than the limit tells an oversized file from one that just fits
+    let mut bytes = Vec::new();
+    reader.take(READ_LIMIT as u64 + 1).read_to_end(&mut bytes).map_err(unreadable)?;
+    check(&bytes)?;
+    Ok(bytes)
+}
+
+/**
+ *  @func   load()      read a source and unpack the rom it holds
+ *
+ *  @param  source      file or stdin
+ * */
+pub fn load(source: Source) -> Result<Rom, Chip8Error> {
+    load_bytes(&read(source)?)
+}
+
+/**
+ *  @func   load_bytes()    unpack the rom held by bytes already in memory
+ *
+ *  @param  bytes           raw rom image or one of the containers of container.rs
+ * */
+pub fn load_bytes(bytes: &[u8]) -> Result<Rom, Chip8Error> {
+    check(bytes)?;
+    let rom = container::unpack(bytes.to_vec())?;
+
+    // a container may hold nothing
+    if rom.image.is_empty() {
+        return Err(Chip8Error::RomEmpty);
+    }
+    Ok(rom)
+}
 
-    Ok(fbuffer)
+/**
+ *  @func   place()     address a rom is loaded to, if it fits there
+ *
+ *  @param  rom         unpacked rom
+ *
+ *  @param  platform    programs may only fill the memory the platform leaves to them
+ *
+ *  @param  entry       address programs are loaded to
+ *
+ *  Memory images bring their own address and are a copy of the whole memory,
+ *  the interpreter's areas included, so they only have to fit into it.
+ * */
+pub fn place(rom: &Rom, platform: Platform, entry: usize) -> Result<usize, Chip8Error> {
+    let (address, end) = match rom.base {
+        Some(base)  => (base, MEMSIZE),
+        None        => (entry, platform.program_end().min(MEMSIZE)),
+    };
+    let max = end.saturating_sub(address);
+
+    if rom.image.len() > max {
+        return Err(Chip8Error::RomTooLarge { size: rom.image.len(), max });
+    }
+    Ok(address)
+}
+
+/**
+ *  @func   check()     reject empty files and ones no rom comes in
+ * */
+fn check(bytes: &[u8]) -> Result<(), Chip8Error> {
+    if bytes.is_empty() {
+        return Err(Chip8Error::RomEmpty);
+    }
+    if bytes.len() > READ_LIMIT {
+        return Err(Chip8Error::FileTooLarge { max: READ_LIMIT });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_rom() {
+        assert!(matches!(load_bytes(&[]), Err(Chip8Error::RomEmpty)));
+
+        // a gzip stream holding nothing
+        let empty_gzip = [0x1F, 0x8B, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+        assert!(matches!(load_bytes(&empty_gzip), Err(Chip8Error::RomEmpty)));
+    }
+
+    #[test]
+    fn oversized_file() {
+        assert!(matches!(load_bytes(&vec![0; READ_LIMIT + 1]), Err(Chip8Error::FileTooLarge { max: READ_LIMIT })));
+    }
+
+    #[test]
+    fn unreadable_file() {
+        match read(Source::Path(Path::new("/nonexistent/pong.ch8"))) {
+            Err(Chip8Error::RomUnreadable { name, err }) => {
+                assert_eq!(name, "/nonexistent/pong.ch8");
+                assert_eq!(err.kind(), io::ErrorKind::NotFound);
+            },
+            other => panic!("expected an unreadable rom, got {:?}", other.map(|bytes| bytes.len())),
+        }
+    }
+
+    #[test]
+    fn size_limit_of_the_platform() {
+        let rom = load_bytes(&vec![0x12; 0xCA0]).unwrap();
+        assert_eq!(place(&rom, Platform::Vip, 0x200).unwrap(), 0x200);
+
+        let rom = load_bytes(&vec![0x12; 0xCA1]).unwrap();
+        assert!(matches!(place(&rom, Platform::Vip, 0x200), Err(Chip8Error::RomTooLarge { size: 0xCA1, max: 0xCA0 })));
+        assert_eq!(place(&rom, Platform::Schip, 0x200).unwrap(), 0x200);
+    }
 }
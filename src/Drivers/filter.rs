@@ -0,0 +1,212 @@
+/*
+ *  ===========================================================
+ *
+ *     Filename:    filter.rs
+ *  Description:    upscale the framebuffer on the CPU, so the
+ *                  window, screenshots and exports look the same
+ *
+ *  ===========================================================
+ * */
+
+use std::io;
+
+use super::display::{HORIZONTAL, VERTICAL};
+use super::phosphor::Shades;
+
+/**
+ *  Filters the framebuffer is upscaled with
+ *  - Nearest   every pixel becomes a square
+ *  - Scale2x   EPX, smooths diagonals at twice the size, then nearest
+ *  - Scale3x   the same at three times the size
+ *  - Scanlines nearest, the last row of every pixel is darkened
+ *  - Crt       scanlines under an RGB aperture grille
+ * */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Filter {
+    Nearest,
+    Scale2x,
+    Scale3x,
+    Scanlines,
+    Crt
+}
+
+impl Filter {
+    /**
+     *  @func   from_name()     filter by its commandline name
+     *
+     *  @param  name            "nearest", "scale2x", "scale3x", "scanlines" or "crt"
+     * */
+    pub fn from_name(name: &str) -> Result<Filter, io::Error> {
+        match name {
+            "nearest"   => Ok(Filter::Nearest),
+            "scale2x"   => Ok(Filter::Scale2x),
+            "scale3x"   => Ok(Filter::Scale3x),
+            "scanlines" => Ok(Filter::Scanlines),
+            "crt"       => Ok(Filter::Crt),
+            _           => Err(io::Error::new(io::ErrorKind::InvalidInput, "Unknown filter")),
+        }
+    }
+
+    /**
+     *  @func   scale()     smallest scale the filter works at that is at least the given one
+     *
+     *  @param  scale       integer scale asked for
+     * */
+    pub fn scale(self, scale: usize) -> usize {
+        let step = match self {
+            Filter::Nearest     => 1,
+            Filter::Scale2x     => 2,
+            Filter::Scale3x     => 3,
+            Filter::Scanlines   => 2,
+            Filter::Crt         => 3,
+        };
+        scale.div_ceil(step) * step
+    }
+
+    /**
+     *  @func   indexed()   the filter only moves shades, its output still fits the palette
+     * */
+    pub fn indexed(self) -> bool {
+        matches!(self, Filter::Nearest | Filter::Scale2x | Filter::Scale3x)
+    }
+
+    /**
+     *  @func   upscale()   shades of the upscaled framebuffer, row by row
+     *
+     *  @param  shades      shades to be scaled
+     *
+     *  @param  scale       integer scale, a multiple of what the filter needs
+     * */
+    pub fn upscale(self, shades: &Shades, scale: usize) -> Vec<u8> {
+        let pixels: Vec<u8> = shades.iter().flatten().copied().collect();
+
+        let (pixels, width, factor) = match self {
+            Filter::Scale2x => (scale2x(&pixels, HORIZONTAL, VERTICAL), 2 * HORIZONTAL, 2),
+            Filter::Scale3x => (scale3x(&pixels, HORIZONTAL, VERTICAL), 3 * HORIZONTAL, 3),
+            _               => (pixels, HORIZONTAL, 1),
+        };
+        nearest(&pixels, width, scale / factor)
+    }
+
+    /**
+     *  @func   shade()     darken the RGB image of the upscaled framebuffer where the filter needs to
+     *
+     *  @param  rgb         8 bit RGB, row by row
+     *
+     *  @param  width       width of the image
+     *
+     *  @param  scale       integer scale the image was upscaled with
+     * */
+    pub fn shade(self, rgb: &mut [u8], width: usize, scale: usize) {
+        if self.indexed() {
+            return;
+        }
+
+        for (y, row) in rgb.chunks_exact_mut(3 * width).enumerate() {
+            // the gap between two lines of the beam
+            let scanline = scale > 1 && y % scale == scale - 1;
+
+            for (x, pixel) in row.chunks_exact_mut(3).enumerate() {
+                for (channel, value) in pixel.iter_mut().enumerate() {
+                    let mut level = if scanline { 0.5 } else { 1.0 };
+
+                    // every column lets one channel through fully
+                    if self == Filter::Crt && x % 3 != channel {
+                        level *= 0.7;
+                    }
+                    *value = (*value as f32 * level) as u8;
+                }
+            }
+        }
+    }
+}
+
+/**
+ *  @func   nearest()   repeat every pixel scale x scale times
+ * */
+fn nearest(pixels: &[u8], width: usize, scale: usize) -> Vec<u8> {
+    let mut scaled = Vec::with_capacity(pixels.len() * scale * scale);
+
+    for line in pixels.chunks_exact(width) {
+        let row: Vec<u8> = line.iter().flat_map(|pixel| vec![*pixel; scale]).collect();
+        for _ in 0..scale {
+            scaled.extend_from_slice(&row);
+        }
+    }
+    scaled
+}
+
+/**
+ *  @func   neighbours()    the 3x3 block around a pixel, the edges are repeated outside of the image
+ * */
+fn neighbours(pixels: &[u8], width: usize, height: usize, x: usize, y: usize) -> [u8; 9] {
+    let mut block = [0; 9];
+
+    for (n, value) in block.iter_mut().enumerate() {
+        let nx = (x + n % 3).saturating_sub(1).min(width - 1);
+        let ny = (y + n / 3).saturating_sub(1).min(height - 1);
+        *value = pixels[ny * width + nx];
+    }
+    block
+}
+
+/**
+ *  @func   scale2x()   EPX / Scale2x, every pixel becomes 2x2
+ * */
+fn scale2x(pixels: &[u8], width: usize, height: usize) -> Vec<u8> {
+    let mut scaled = vec![0; pixels.len() * 4];
+
+    for y in 0..height {
+        for x in 0..width {
+            let [_, b, _, d, e, f, _, h, _] = neighbours(pixels, width, height, x, y);
+
+            let block = if b != h && d != f {
+                [
+                    if d == b { d } else { e }, if b == f { f } else { e },
+                    if d == h { d } else { e }, if h == f { f } else { e },
+                ]
+            } else {
+                [e; 4]
+            };
+
+            for (n, value) in block.iter().enumerate() {
+                scaled[(2 * y + n / 2) * 2 * width + 2 * x + n % 2] = *value;
+            }
+        }
+    }
+    scaled
+}
+
+/**
+ *  @func   scale3x()   AdvMAME3x / Scale3x, every pixel becomes 3x3
+ * */
+fn scale3x(pixels: &[u8], width: usize, height: usize) -> Vec<u8> {
+    let mut scaled = vec![0; pixels.len() * 9];
+
+    for y in 0..height {
+        for x in 0..width {
+            let [a, b, c, d, e, f, g, h, i] = neighbours(pixels, width, height, x, y);
+
+            let block = if b != h && d != f {
+                [
+                    if d == b { d } else { e },
+                    if (d == b && e != c) || (b == f && e != a) { b } else { e },
+                    if b == f { f } else { e },
+                    if (d == b && e != g) || (d == h && e != a) { d } else { e },
+                    e,
+                    if (b == f && e != i) || (h == f && e != c) { f } else { e },
+                    if d == h { d } else { e },
+                    if (d == h && e != i) || (h == f && e != g) { h } else { e },
+                    if h == f { f } else { e },
+                ]
+            } else {
+                [e; 9]
+            };
+
+            for (n, value) in block.iter().enumerate() {
+                scaled[(3 * y + n / 3) * 3 * width + 3 * x + n % 3] = *value;
+            }
+        }
+    }
+    scaled
+}
@@ -0,0 +1,88 @@
+/*
+ *  ===========================================================
+ *
+ *     Filename:    frontend.rs
+ *  Description:    interface between the machine loop and the
+ *                  window, terminal or files it presents to
+ *
+ *  ===========================================================
+ * */
+
+use std::io;
+
+use super::keyboard_io::Keypad;
+use super::phosphor::Shades;
+use super::speed::Speed;
+
+/**
+ *  A place the emulated frames are presented to, driven once per host frame by the machine loop
+ *
+ *  The loop waits for the next host frame, polls for input, runs as many emulated
+ *  frames as the speed asks for and reports the sound and the shades the phosphor
+ *  made of the framebuffer after each of them. Frontends that show frames should
+ *  only keep the presented shades and draw the last of them when polled, so a host
+ *  that falls behind drops shown frames but never emulated ones.
+ * */
+pub trait Frontend {
+    /**
+     *  @func   poll        draw the last presented frame and apply the input that arrived to the keypad
+     *
+     *  @param  keypad      host keypad, kept between frames
+     *
+     *  @param  speed       pause, frame advance, speed and instructions per frame the user controls
+     *
+     *  @param  frame       frame about to be executed
+     *
+     *  returns false once the user asked to quit
+     * */
+    fn poll(&mut self, keypad: &mut Keypad, speed: &mut Speed, frame: u64) -> Result<bool, io::Error>;
+
+    /**
+     *  @func   present     show a frame
+     *
+     *  @param  shades      shades of the framebuffer after the frame
+     *
+     *  @param  frame       number of the frame that just ran
+     * */
+    fn present(&mut self, shades: &Shades, frame: u64) -> Result<(), io::Error>;
+
+    /**
+     *  @func   paced       the loop waits for the host frames, false runs as fast as possible
+     * */
+    fn paced(&self) -> bool {
+        true
+    }
+
+    /**
+     *  @func   sound       state of the buzzer after a frame
+     *
+     *  @param  on          the sound timer is running
+     * */
+    fn sound(&mut self, _on: bool) {}
+
+    /**
+     *  @func   finish      flush what the frontend still holds, called once the loop ended
+     * */
+    fn finish(&mut self) -> Result<(), io::Error> {
+        Ok(())
+    }
+}
+
+/**
+ *  Runs as fast as the host allows, nobody is at the keyboard and nothing is shown
+ * */
+pub struct Headless;
+
+impl Frontend for Headless {
+    fn poll(&mut self, _keypad: &mut Keypad, _speed: &mut Speed, _frame: u64) -> Result<bool, io::Error> {
+        Ok(true)
+    }
+
+    fn present(&mut self, _shades: &Shades, _frame: u64) -> Result<(), io::Error> {
+        Ok(())
+    }
+
+    fn paced(&self) -> bool {
+        false
+    }
+}
@@ -0,0 +1,266 @@
+/*
+ *  ===========================================================
+ *
+ *     Filename:    hexfile.rs
+ *  Description:    memory images as Intel HEX or Motorola
+ *                  S-records, as used by 1802 and VIP tools
+ *
+ *  ===========================================================
+ *
+ *  Intel HEX:  ":" count address type data checksum, the checksum makes the sum of all
+ *              bytes of the record 0. Data (00), end of file (01), extended segment (02)
+ *              and extended linear addresses (04) are understood, start addresses skipped.
+ *
+ *  S-record:   "S" type count address data checksum, the checksum is the complement of
+ *              the sum of count, address and data. S1 to S3 hold data at 16, 24 and 32 bit
+ *              addresses, S7 to S9 end the file, headers and counts are skipped.
+ *
+ *  Both are written with 16 bytes per record.
+ * */
+
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+
+// highest address + 1 a memory image may fill, all of XO-CHIP's memory
+const MAX_SIZE: usize = 0x10000;
+
+// data bytes per written record
+const RECORD: usize = 16;
+
+/**
+ *  Formats of memory images
+ *  - Intel     Intel HEX
+ *  - Srec      Motorola S-records
+ * */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HexFormat {
+    Intel,
+    Srec
+}
+
+impl HexFormat {
+    /**
+     *  @func   detect()        format of a file by its content
+     *
+     *  @param  bytes           content of the file
+     * */
+    pub fn detect(bytes: &[u8]) -> Option<HexFormat> {
+        let first = bytes.iter().position(|b| !b.is_ascii_whitespace())?;
+
+        match &bytes[first..] {
+            [b':', b, ..] if b.is_ascii_hexdigit()              => Some(HexFormat::Intel),
+            [b'S', b'0'..=b'9', b, ..] if b.is_ascii_hexdigit() => Some(HexFormat::Srec),
+            _                                                   => None,
+        }
+    }
+
+    /**
+     *  @func   from_filename() format to write by the extension of a file
+     *
+     *  @param  filename        ".hex", ".ihex" or ".ihx" for Intel HEX, ".srec", ".s19" or ".mot" for S-records
+     * */
+    pub fn from_filename(filename: &str) -> Result<HexFormat, io::Error> {
+        let extension = filename.rsplit('.').next().unwrap_or("").to_lowercase();
+
+        match extension.as_str() {
+            "hex" | "ihex" | "ihx"  => Ok(HexFormat::Intel),
+            "srec" | "s19" | "mot"  => Ok(HexFormat::Srec),
+            _                       => Err(io::Error::new(io::ErrorKind::InvalidInput, "Unknown memory image format")),
+        }
+    }
+
+    /**
+     *  @func   name()          name shown to the user
+     * */
+    pub fn name(self) -> &'static str {
+        match self {
+            HexFormat::Intel    => "Intel HEX",
+            HexFormat::Srec     => "S-record",
+        }
+    }
+}
+
+/**
+ *  @func   parse()     bytes of a memory image, from its lowest address on
+ *
+ *  @param  text        content of an Intel HEX or S-record file
+ *
+ *  returns the lowest address and the bytes from there, gaps between the records are 0
+ * */
+pub fn parse(text: &[u8]) -> Result<(usize, Vec<u8>), io::Error> {
+    let format = HexFormat::detect(text).ok_or_else(|| invalid(0, "is no Intel HEX or S-record"))?;
+    let text = std::str::from_utf8(text).map_err(|_| invalid(0, "isn't text"))?;
+
+    let mut segments: Vec<(usize, Vec<u8>)> = Vec::new();
+    let mut base = 0;
+
+    for (n, line) in text.lines().map(str::trim).enumerate().filter(|(_, line)| !line.is_empty()) {
+        let record = match format {
+            HexFormat::Intel    => line.strip_prefix(':'),
+            HexFormat::Srec     => line.strip_prefix('S').and_then(|record| record.get(1..)),
+        };
+        let bytes = record.and_then(decode).ok_or_else(|| invalid(n + 1, "is malformed"))?;
+
+        // address and bytes of data records, the end of the file stops reading
+        let data = match format {
+            HexFormat::Intel => {
+                if bytes.len() < 5 || bytes[0] as usize != bytes.len() - 5 {
+                    return Err(invalid(n + 1, "has a wrong length"));
+                }
+                if bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) != 0 {
+                    return Err(invalid(n + 1, "has a wrong checksum"));
+                }
+
+                let address = (bytes[1] as usize) << 8 | bytes[2] as usize;
+                let data = &bytes[4..bytes.len() - 1];
+                match bytes[3] {
+                    0x00 => Some((base + address, data)),
+                    0x01 => break,
+                    0x02 => { base = be(data) << 4; None },
+                    0x04 => { base = be(data) << 16; None },
+                    _    => None,
+                }
+            },
+            HexFormat::Srec => {
+                if bytes.is_empty() || bytes[0] as usize != bytes.len() - 1 {
+                    return Err(invalid(n + 1, "has a wrong length"));
+                }
+                if bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) != 0xFF {
+                    return Err(invalid(n + 1, "has a wrong checksum"));
+                }
+
+                let width = match line.as_bytes()[1] {
+                    b'1' => 2,
+                    b'2' => 3,
+                    b'3' => 4,
+                    b'7' | b'8' | b'9' => break,
+                    _    => continue,
+                };
+                let data = bytes.get(1 + width..bytes.len() - 1).ok_or_else(|| invalid(n + 1, "has a wrong length"))?;
+                Some((be(&bytes[1..1 + width]), data))
+            },
+        };
+
+        if let Some((address, data)) = data {
+            if address + data.len() > MAX_SIZE {
+                return Err(invalid(n + 1, "is beyond the memory"));
+            }
+            segments.push((address, data.to_vec()));
+        }
+    }
+
+    let start = segments.iter().map(|(address, _)| *address).min().ok_or_else(|| invalid(0, "holds no data"))?;
+    let end = segments.iter().map(|(address, data)| address + data.len()).max().unwrap_or(start);
+
+    let mut image = vec![0; end - start];
+    for (address, data) in segments {
+        image[address - start..address - start + data.len()].copy_from_slice(&data);
+    }
+    Ok((start, image))
+}
+
+/**
+ *  @func   write()     write a range of memory as a memory image
+ *
+ *  @param  format      Intel HEX or S-records
+ *
+ *  @param  filename    file to be written
+ *
+ *  @param  address     address of the first byte
+ *
+ *  @param  bytes       memory from address on
+ * */
+pub fn write(format: HexFormat, filename: &str, address: usize, bytes: &[u8]) -> Result<(), io::Error> {
+    if address + bytes.len() > MAX_SIZE {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Memory image is beyond 16 bit addresses"));
+    }
+    let mut writer = io::BufWriter::new(fs::File::create(filename)?);
+
+    for (n, data) in bytes.chunks(RECORD).enumerate() {
+        let at = address + n * RECORD;
+
+        match format {
+            HexFormat::Intel => {
+                let mut record = vec![data.len() as u8, (at >> 8) as u8, at as u8, 0x00];
+                record.extend_from_slice(data);
+                writeln!(writer, ":{}", encode(&record, |sum| sum.wrapping_neg()))?;
+            },
+            HexFormat::Srec => {
+                let mut record = vec![data.len() as u8 + 3, (at >> 8) as u8, at as u8];
+                record.extend_from_slice(data);
+                writeln!(writer, "S1{}", encode(&record, |sum| !sum))?;
+            },
+        }
+    }
+
+    match format {
+        HexFormat::Intel    => writeln!(writer, ":00000001FF")?,
+        HexFormat::Srec     => writeln!(writer, "S9030000FC")?,
+    }
+    writer.flush()
+}
+
+fn invalid(line: usize, msg: &str) -> io::Error {
+    match line {
+        0 => io::Error::new(io::ErrorKind::InvalidData, format!("Memory image {}", msg)),
+        _ => io::Error::new(io::ErrorKind::InvalidData, format!("Memory image record in line {} {}", line, msg)),
+    }
+}
+
+/**
+ *  @func   decode()    bytes of hex digits, None if they aren't pairs of hex digits
+ * */
+fn decode(digits: &str) -> Option<Vec<u8>> {
+    if !digits.len().is_multiple_of(2) || !digits.is_ascii() {
+        return None;
+    }
+    (0..digits.len()).step_by(2).map(|at| u8::from_str_radix(&digits[at..at + 2], 16).ok()).collect()
+}
+
+/**
+ *  @func   encode()    hex digits of a record followed by its checksum
+ *
+ *  @param  checksum    checksum of the sum of the record's bytes
+ * */
+fn encode(record: &[u8], checksum: fn(u8) -> u8) -> String {
+    let sum = record.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+    record.iter().chain([checksum(sum)].iter()).map(|byte| format!("{:02X}", byte)).collect()
+}
+
+/**
+ *  @func   be()        big endian number of up to 4 bytes
+ * */
+fn be(bytes: &[u8]) -> usize {
+    bytes.iter().fold(0, |value, byte| value << 8 | *byte as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intel_record_with_good_checksum() {
+        assert_eq!(parse(b":0300300002337A1E\n:00000001FF\n").unwrap(), (0x30, vec![0x02, 0x33, 0x7A]));
+    }
+
+    #[test]
+    fn intel_record_with_bad_checksum() {
+        let err = parse(b":0300300002337A1F\n").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "Memory image record in line 1 has a wrong checksum");
+    }
+
+    #[test]
+    fn s1_record_with_good_checksum() {
+        let (start, image) = parse(b"S1130000285F245F2212226A000424290008237C2A\nS9030000FC\n").unwrap();
+        assert_eq!(start, 0x0000);
+        assert_eq!(image, [0x28, 0x5F, 0x24, 0x5F, 0x22, 0x12, 0x22, 0x6A, 0x00, 0x04, 0x24, 0x29, 0x00, 0x08, 0x23, 0x7C]);
+    }
+
+    #[test]
+    fn s1_record_with_bad_checksum() {
+        let err = parse(b"S1130000285F245F2212226A000424290008237C2B\n").unwrap_err();
+        assert_eq!(err.to_string(), "Memory image record in line 1 has a wrong checksum");
+    }
+}
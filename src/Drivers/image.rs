@@ -0,0 +1,73 @@
+/*
+ *  ===========================================================
+ *
+ *     Filename:    image.rs
+ *  Description:    scaled RGB images of the framebuffer for
+ *                  screenshots and exports
+ *
+ *  ===========================================================
+ * */
+
+extern crate png;
+
+use std::fs;
+use std::io;
+
+use super::display::{HORIZONTAL, VERTICAL};
+use super::filter::Filter;
+use super::palette::Palette;
+use super::phosphor::Shades;
+
+/**
+ *  Framebuffer rendered to 8 bit RGB, every pixel scaled to scale x scale
+ * */
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub rgb: Vec<u8>
+}
+
+impl Image {
+    /**
+     *  @func   from_shades()   render the framebuffer as shown
+     *
+     *  @param  shades          shades of the framebuffer after blending
+     *
+     *  @param  scale           integer scale, 1 keeps one image pixel per CHIP-8 pixel
+     *
+     *  @param  palette         colors of the pixel values
+     *
+     *  @param  filter          filter the framebuffer is upscaled with
+     * */
+    pub fn from_shades(shades: &Shades, scale: usize, palette: &Palette, filter: Filter) -> Image {
+        let width = HORIZONTAL * scale;
+        let mut rgb: Vec<u8> = filter.upscale(shades, scale)
+            .iter()
+            .flat_map(|shade| palette.shade(*shade))
+            .collect();
+        filter.shade(&mut rgb, width, scale);
+
+        Image {
+            width,
+            height: VERTICAL * scale,
+            rgb
+        }
+    }
+
+    /**
+     *  @func   write_png()     save the image as PNG
+     *
+     *  @param  filename        PNG to be written
+     * */
+    pub fn write_png(&self, filename: &str) -> Result<(), io::Error> {
+        let file = io::BufWriter::new(fs::File::create(filename)?);
+
+        let mut encoder = png::Encoder::new(file, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        encoder.write_header()
+            .and_then(|mut writer| writer.write_image_data(&self.rgb))
+            .map_err(io::Error::other)
+    }
+}
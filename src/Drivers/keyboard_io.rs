@@ -0,0 +1,194 @@
+use crossterm::event::KeyCode;
+use piston::input::Key;
+use std::io;
+
+/**
+ *  Keypad:
+ *  - 16 keys 0x0 to 0xF
+ *  - state is stored as a bitmask, bit n set -> key n pressed
+ *
+ *  Host keyboard layout:
+ *      1 2 3 4         1 2 3 C
+ *      Q W E R   ->    4 5 6 D
+ *      A S D F         7 8 9 E
+ *      Z X C V         A 0 B F
+ * */
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Keypad {
+    pub state: u16
+}
+
+impl Keypad {
+    /**
+     *  @func   new()   Create new keypad with all keys released
+     * */
+    pub fn new() -> Keypad {
+        Keypad {
+            state: 0x0000
+        }
+    }
+
+    /**
+     *  @func   press       mark key as pressed
+     *
+     *  @param  key         key denominator (0-F)
+     * */
+    pub fn press(&mut self, key: u8) {
+        self.state |= 1 << (key & 0xF);
+    }
+
+    /**
+     *  @func   release     mark key as released
+     *
+     *  @param  key         key denominator (0-F)
+     * */
+    pub fn release(&mut self, key: u8) {
+        self.state &= !(1 << (key & 0xF));
+    }
+
+    /**
+     *  @func   is_pressed  check if key is currently held down
+     *
+     *  @param  key         key denominator (0-F)
+     * */
+    pub fn is_pressed(&self, key: u8) -> bool {
+        self.state & (1 << (key & 0xF)) != 0
+    }
+
+    /**
+     *  @func   first_pressed   lowest key that is currently held down
+     * */
+    pub fn first_pressed(&self) -> Option<u8> {
+        (0..16).find(|&key| self.is_pressed(key))
+    }
+
+    /**
+     *  @func   map_key     translate host key to keypad key
+     *
+     *  @param  key         piston key that was pressed/released
+     * */
+    pub fn map_key(key: Key) -> Option<u8> {
+        match key {
+            Key::D1 => Some(0x1),
+            Key::D2 => Some(0x2),
+            Key::D3 => Some(0x3),
+            Key::D4 => Some(0xC),
+            Key::Q  => Some(0x4),
+            Key::W  => Some(0x5),
+            Key::E  => Some(0x6),
+            Key::R  => Some(0xD),
+            Key::A  => Some(0x7),
+            Key::S  => Some(0x8),
+            Key::D  => Some(0x9),
+            Key::F  => Some(0xE),
+            Key::Z  => Some(0xA),
+            Key::X  => Some(0x0),
+            Key::C  => Some(0xB),
+            Key::V  => Some(0xF),
+            _       => None,
+        }
+    }
+
+    /**
+     *  @func   map_char    translate a character typed in a terminal to keypad key
+     *
+     *  @param  c           character read from the terminal, same layout as map_key
+     * */
+    pub fn map_char(c: char) -> Option<u8> {
+        match c.to_ascii_lowercase() {
+            '1' => Some(0x1),
+            '2' => Some(0x2),
+            '3' => Some(0x3),
+            '4' => Some(0xC),
+            'q' => Some(0x4),
+            'w' => Some(0x5),
+            'e' => Some(0x6),
+            'r' => Some(0xD),
+            'a' => Some(0x7),
+            's' => Some(0x8),
+            'd' => Some(0x9),
+            'f' => Some(0xE),
+            'z' => Some(0xA),
+            'x' => Some(0x0),
+            'c' => Some(0xB),
+            'v' => Some(0xF),
+            _   => None,
+        }
+    }
+}
+
+/**
+ *  Keypad keys pressed by the arrow keys, space (a) and enter (b) on top of the keypad layout,
+ *  named like the keys of the ROM database
+ * */
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct KeyMap {
+    pub up: Option<u8>,
+    pub down: Option<u8>,
+    pub left: Option<u8>,
+    pub right: Option<u8>,
+    pub a: Option<u8>,
+    pub b: Option<u8>
+}
+
+impl KeyMap {
+    /**
+     *  @func   set_keys()  bind keys from the commandline
+     *
+     *  @param  keys        comma separated name=key, e.g. "up=5,down=8,a=6", keys in hex
+     * */
+    pub fn set_keys(&mut self, keys: &str) -> Result<(), io::Error> {
+        let unknown = || io::Error::new(io::ErrorKind::InvalidInput, "Unknown key binding");
+
+        for binding in keys.split(',') {
+            let (name, key) = binding.split_once('=').ok_or_else(unknown)?;
+            let key = u8::from_str_radix(key, 16).ok().filter(|key| *key < 16).ok_or_else(unknown)?;
+
+            match name {
+                "up"    => self.up = Some(key),
+                "down"  => self.down = Some(key),
+                "left"  => self.left = Some(key),
+                "right" => self.right = Some(key),
+                "a"     => self.a = Some(key),
+                "b"     => self.b = Some(key),
+                _       => return Err(unknown()),
+            }
+        }
+        Ok(())
+    }
+
+    /**
+     *  @func   map_key     translate host key to keypad key, the keypad layout first
+     *
+     *  @param  key         piston key that was pressed/released
+     * */
+    pub fn map_key(&self, key: Key) -> Option<u8> {
+        Keypad::map_key(key).or(match key {
+            Key::Up     => self.up,
+            Key::Down   => self.down,
+            Key::Left   => self.left,
+            Key::Right  => self.right,
+            Key::Space  => self.a,
+            Key::Return => self.b,
+            _           => None,
+        })
+    }
+
+    /**
+     *  @func   map_code    translate a key read from a terminal to keypad key, the keypad layout first
+     *
+     *  @param  code        crossterm key code
+     * */
+    pub fn map_code(&self, code: KeyCode) -> Option<u8> {
+        match code {
+            KeyCode::Char(' ')  => self.a,
+            KeyCode::Char(c)    => Keypad::map_char(c),
+            KeyCode::Up         => self.up,
+            KeyCode::Down       => self.down,
+            KeyCode::Left       => self.left,
+            KeyCode::Right      => self.right,
+            KeyCode::Enter      => self.b,
+            _                   => None,
+        }
+    }
+}
@@ -1,7 +1,32 @@
-use std::io;
+use std::fmt;
+use std::ops::Range;
 
-const MEMSIZE: usize = 4095;                  // Size of the total memory
-const STACKSIZE: usize = 12;                   // Size of the Stack
+use super::super::error::Chip8Error;
+
+pub const MEMSIZE: usize = 4096;               // Size of the total memory
+pub const STACKSIZE: usize = 12;               // Default size of the Stack (VIP)
+
+/**
+ *  Font sprites for the hex digits 0-F, each 4x5 pixels
+ * */
+pub const FONTSET: [u8; 80] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0,   // 0
+    0x20, 0x60, 0x20, 0x20, 0x70,   // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0,   // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0,   // 3
+    0x90, 0x90, 0xF0, 0x10, 0x10,   // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0,   // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0,   // 6
+    0xF0, 0x10, 0x20, 0x40, 0x40,   // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0,   // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0,   // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90,   // A
+    0xE0, 0x90, 0xE0, 0x90, 0xE0,   // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0,   // C
+    0xE0, 0x90, 0x90, 0x90, 0xE0,   // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0,   // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80,   // F
+];
 
 
 pub enum MODE<T> {
@@ -13,15 +38,79 @@ pub enum MODE<T> {
  *  - CHIP-8 offers 4K aka. 4096 memory location of which each can hold 8 bits
  *  - 0x0000 -> 0x200   Font or Interpreter Data
  *  - 0x200 -> 0xEA0    Program Data
- *  - 0xEA0 -> 0xEFF    Call Stack
- *  - 0xEFF -> 0xFFF    Display refresh
+ *  - 0xEA0 -> 0xF00    Call Stack
+ *  - 0xF00 -> 0xFFF    Display refresh
+ *
+ *  The call stack and the display live outside of mem (call_stack, framebuffer),
+ *  their regions are only named so faults can be reported. The CDP1802 core
+ *  mirrors V0-VF and the display into them for 0NNN routines.
  * */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Region {
+    Interpreter,
+    Program,
+    Stack,
+    Display
+}
+
+impl Region {
+    /**
+     *  @func   range()     addresses covered by the region
+     * */
+    pub fn range(self) -> Range<usize> {
+        match self {
+            Region::Interpreter => 0x000..0x200,
+            Region::Program     => 0x200..0xEA0,
+            Region::Stack       => 0xEA0..0xF00,
+            Region::Display     => 0xF00..MEMSIZE,
+        }
+    }
+
+    /**
+     *  @func   of()            region an address belongs to
+     *
+     *  @param  mem_address     address to look up
+     * */
+    pub fn of(mem_address: usize) -> Option<Region> {
+        [Region::Interpreter, Region::Program, Region::Stack, Region::Display]
+            .iter()
+            .copied()
+            .find(|region| region.range().contains(&mem_address))
+    }
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Region::Interpreter => "interpreter/font",
+            Region::Program     => "program",
+            Region::Stack       => "stack",
+            Region::Display     => "display",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/**
+ *  Reason for a Chip8Error::MemoryFault
+ * */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FaultKind {
+    OutOfBounds,
+    WriteProtected
+}
 
 #[derive(Debug)]
 pub struct Memory {
     // define memory
     pub mem: [u8; MEMSIZE],
-    pub call_stack: Vec<u16>
+    pub call_stack: Vec<u16>,
+
+    // number of return addresses the call stack can hold
+    pub stack_depth: usize,
+
+    // reject writes to the interpreter/font region
+    pub protect_font: bool
 }
 
 impl Memory {
@@ -32,7 +121,11 @@ impl Memory {
         // return an instance of memory
         Memory {
             mem: [0x00; MEMSIZE],
-            call_stack: Vec::new()
+            call_stack: Vec::new(),
+
+            stack_depth: STACKSIZE,
+
+            protect_font: false
         }
     }
     
@@ -43,18 +136,18 @@ impl Memory {
      *  @param  img     image to be loaded
      *
      *  @param  offset  from memory 0x0000
+     *
+     *  loading is done by the host and ignores the write protection
      * */
-    pub fn load(&mut self, img: Vec<u8>, offset: usize) -> Result<(), io::Error>{
+    pub fn load(&mut self, img: Vec<u8>, offset: usize) -> Result<(), Chip8Error>{
 
         // failsafe
-        if img.len() > MEMSIZE  {
-           return Err(io::Error::new(io::ErrorKind::Other, "Invalid image size")); 
+        if offset + img.len() > MEMSIZE  {
+           return Err(Chip8Error::RomTooLarge { size: img.len(), max: MEMSIZE.saturating_sub(offset) });
         }
 
-        // iterate through array 
-        for n in 0..img.len() {
-            self.mem[n+offset] = img[n];
-        }
+        // copy image into memory
+        self.mem[offset..offset+img.len()].copy_from_slice(&img);
 
         Ok(())
     }
@@ -66,10 +159,10 @@ impl Memory {
      *
      *  @param  mode            access mode WRITE includes the data to be written
      * */
-    pub fn rw_memory(&mut self, mem_address: usize, mode: MODE<u8>) -> Result<u8, io::Error> {
+    pub fn rw_memory(&mut self, mem_address: usize, mode: MODE<u8>) -> Result<u8, Chip8Error> {
 
-        if mem_address > MEMSIZE {
-            return Err(io::Error::new(io::ErrorKind::Other, "Memory out of bounds"));
+        if mem_address >= MEMSIZE {
+            return Err(Chip8Error::MemoryFault { pc: None, addr: mem_address, kind: FaultKind::OutOfBounds });
         } 
 
         match mode {
@@ -77,6 +170,10 @@ impl Memory {
                Ok(self.mem[mem_address])
             },
             MODE::WRITE(value)  => {
+                if self.protect_font && Region::of(mem_address) == Some(Region::Interpreter) {
+                    return Err(Chip8Error::MemoryFault { pc: None, addr: mem_address, kind: FaultKind::WriteProtected });
+                }
+
                 self.mem[mem_address] = value;
                 Ok(value)
             }
@@ -88,21 +185,30 @@ impl Memory {
      *
      *  @param  ret     return address to push
      * */
-    pub fn push(&mut self, ret: u16) {
-        if self.call_stack.len() < STACKSIZE {
-            self.call_stack.push(ret);
+    pub fn push(&mut self, ret: u16) -> Result<(), Chip8Error> {
+        if self.call_stack.len() >= self.stack_depth {
+            return Err(Chip8Error::StackOverflow { pc: None, stack: self.stack().to_vec() });
         }
+
+        self.call_stack.push(ret);
+        Ok(())
     }
     
     /**
      *  @func   pop     pop the last value of the stack
      * */
-    pub fn pop(&mut self) -> Option<u16>{
-        if self.call_stack.len() > 0 {
-            self.call_stack.pop()
-        } else {
-            None
-        } 
+    pub fn pop(&mut self) -> Result<u16, Chip8Error> {
+        match self.call_stack.pop() {
+            Some(ret)   => Ok(ret),
+            None        => Err(Chip8Error::StackUnderflow { pc: None }),
+        }
+    }
+
+    /**
+     *  @func   stack   return addresses on the stack, innermost call last
+     * */
+    pub fn stack(&self) -> &[u16] {
+        &self.call_stack
     }
 
 }
@@ -112,11 +218,16 @@ impl Memory {
  *  - V0 to VF (1B)
  *  - VF doubles as flag
  *  - address register I (2B)
+ *  - delay and sound timer (1B), decremented at 60Hz
  * */
+#[derive(Debug)]
 pub struct Registers {
     pub register_array: [u8; 16],
     pub address_register: u16,
 
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+
     pub eip: u16
 }
 
@@ -129,6 +240,9 @@ impl Registers {
             register_array: [0x00; 16],
             address_register: 0x0000,
 
+            delay_timer: 0x00,
+            sound_timer: 0x00,
+
             eip: entry
         }
     }
@@ -140,7 +254,7 @@ impl Registers {
      *
      *  @param  mode            Access mode (read/write)
      * */
-    pub fn rw_register(&mut self, register_flag: usize, mode: MODE<u8>) -> Result<u8, io::Error>{
+    pub fn rw_register(&mut self, register_flag: usize, mode: MODE<u8>) -> Result<u8, Chip8Error>{
         match mode {
             MODE::READ          => {
                 Ok(self.register_array[register_flag])
@@ -0,0 +1,537 @@
+/*
+ *  ===========================================================
+ *
+ *     Filename:    movie.rs
+ *  Description:    record keypad input per frame and play it
+ *                  back deterministically
+ *
+ *  ===========================================================
+ *
+ *  Movie file layout (plain text, one entry per line):
+ *
+ *      CHIP8-MOVIE 1
+ *      rom <fnv1a hash of the rom image>
+ *      boot <fnv1a hash of the interpreter image>   only if one was booted
+ *      base <address the rom is loaded to, in hex>
+ *      quirks shift_lsb=<0|1> stack=<stack depth> protect_font=<0|1>
+ *      rng <xorshift|vip>
+ *      seed <rng seed>
+ *      ipf <instructions per frame>
+ *      timing <ipf|vip>
+ *      policies machine_code=<policy> unknown=<policy>
+ *      interval <frames between state hashes>
+ *      k <frame> <keypad state>        keypad changed before <frame> ran
+ *      h <frame> <state hash>          machine state after <frame> frames
+ *      end <frame>                     number of recorded frames
+ * */
+
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+
+use super::keyboard_io::Keypad;
+use super::super::Interpreter::machine::{fnv1a, Machine};
+use super::super::Interpreter::opcode::Policy;
+use super::super::Interpreter::timing::Timing;
+
+const MAGIC: &str = "CHIP8-MOVIE 1";
+
+// where roms went before movies recorded their base
+const ENTRY: usize = 0x200;
+
+/**
+ *  Everything needed to recreate the machine a movie was recorded on
+ * */
+#[derive(Debug, Clone, PartialEq)]
+pub struct MovieHeader {
+    pub rom_hash: u64,
+    pub boot_hash: Option<u64>,
+    pub base: usize,
+    pub shift_lsb: bool,
+    pub stack_depth: usize,
+    pub protect_font: bool,
+    pub rng: String,
+    pub seed: u64,
+    pub ipf: u32,
+    pub timing: Timing,
+    pub machine_code_policy: Policy,
+    pub unknown_policy: Policy,
+    pub hash_interval: u64
+}
+
+impl MovieHeader {
+    /**
+     *  @func   rom_hash()  hash identifying a rom image
+     *
+     *  @param  image       raw rom image
+     * */
+    pub fn rom_hash(image: &[u8]) -> u64 {
+        fnv1a(None, image)
+    }
+
+    /**
+     *  @func   verify()    check that the movie was recorded with this rom
+     *
+     *  @param  image       raw rom image about to be played
+     *
+     *  @param  base        address the rom is about to be loaded to
+     *
+     *  @param  boot        interpreter image booted on the CDP1802, if any
+     * */
+    pub fn verify(&self, image: &[u8], base: usize, boot: Option<&[u8]>) -> Result<(), io::Error> {
+        if MovieHeader::rom_hash(image) != self.rom_hash {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Movie was recorded with a different rom"));
+        }
+        if base != self.base {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                format!("Movie was recorded with the rom at {:#05x}, not {:#05x}", self.base, base)));
+        }
+        if boot.map(MovieHeader::rom_hash) != self.boot_hash {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Movie was recorded with a different interpreter image"));
+        }
+        if self.unknown_policy == Policy::Native {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Movie runs unknown opcodes natively, only 0NNN can be"));
+        }
+        Ok(())
+    }
+}
+
+/**
+ *  Writes keypad changes and periodic state hashes to a movie file
+ * */
+pub struct MovieRecorder {
+    writer: io::BufWriter<fs::File>,
+    hash_interval: u64,
+    last_keys: Option<u16>
+}
+
+impl MovieRecorder {
+    /**
+     *  @func   create      create movie file and write the header
+     *
+     *  @param  filename    movie to be written
+     *
+     *  @param  header      rom, quirks and seed of the recorded machine
+     * */
+    pub fn create(filename: &str, header: &MovieHeader) -> Result<MovieRecorder, io::Error> {
+        let mut writer = io::BufWriter::new(fs::File::create(filename)?);
+
+        writeln!(writer, "{}", MAGIC)?;
+        writeln!(writer, "rom {:016x}", header.rom_hash)?;
+        if let Some(boot_hash) = header.boot_hash {
+            writeln!(writer, "boot {:016x}", boot_hash)?;
+        }
+        writeln!(writer, "base {:04x}", header.base)?;
+        writeln!(writer, "quirks shift_lsb={} stack={} protect_font={}", header.shift_lsb as u8, header.stack_depth, header.protect_font as u8)?;
+        writeln!(writer, "rng {}", header.rng)?;
+        writeln!(writer, "seed {}", header.seed)?;
+        writeln!(writer, "ipf {}", header.ipf)?;
+        writeln!(writer, "timing {}", header.timing.name())?;
+        writeln!(writer, "policies machine_code={} unknown={}", header.machine_code_policy.name(), header.unknown_policy.name())?;
+        writeln!(writer, "interval {}", header.hash_interval)?;
+
+        Ok(MovieRecorder {
+            writer,
+            hash_interval: header.hash_interval,
+            last_keys: None
+        })
+    }
+
+    /**
+     *  @func   record_input    store the keypad state if it changed
+     *
+     *  @param  frame           frame about to be executed
+     *
+     *  @param  keypad          keypad state for that frame
+     * */
+    pub fn record_input(&mut self, frame: u64, keypad: Keypad) -> Result<(), io::Error> {
+        if self.last_keys != Some(keypad.state) {
+            writeln!(self.writer, "k {} {:04x}", frame, keypad.state)?;
+            self.last_keys = Some(keypad.state);
+        }
+        Ok(())
+    }
+
+    /**
+     *  @func   record_state    store the state hash every hash_interval frames
+     *
+     *  @param  machine         machine after executing a frame
+     * */
+    pub fn record_state(&mut self, machine: &Machine) -> Result<(), io::Error> {
+        if self.hash_interval > 0 && machine.frame.is_multiple_of(self.hash_interval) {
+            writeln!(self.writer, "h {} {:016x}", machine.frame, machine.state_hash())?;
+        }
+        Ok(())
+    }
+
+    /**
+     *  @func   finish      mark the end of the movie and flush it to disk
+     *
+     *  @param  frame       number of frames that were recorded
+     * */
+    pub fn finish(mut self, frame: u64) -> Result<(), io::Error> {
+        writeln!(self.writer, "end {}", frame)?;
+        self.writer.flush()
+    }
+}
+
+/**
+ *  Feeds recorded keypad states back into the machine and checks the state hashes
+ * */
+pub struct MoviePlayer {
+    pub header: MovieHeader,
+    inputs: Vec<(u64, u16)>,
+    hashes: Vec<(u64, u64)>,
+    end: u64,
+
+    next_input: usize,
+    next_hash: usize,
+    keypad: Keypad
+}
+
+impl MoviePlayer {
+    /**
+     *  @func   open        read and parse a movie file
+     *
+     *  @param  filename    movie to be played
+     * */
+    pub fn open(filename: &str) -> Result<MoviePlayer, io::Error> {
+        let content = fs::read_to_string(filename)?;
+        let mut lines = content.lines();
+
+        if lines.next() != Some(MAGIC) {
+            return Err(invalid("Not a movie file"));
+        }
+
+        let mut rom_hash = None;
+        let mut boot_hash = None;
+        let mut base = None;
+        let mut shift_lsb = None;
+        let mut stack_depth = None;
+        let mut protect_font = None;
+        let mut rng = None;
+        let mut seed = None;
+        let mut ipf = None;
+        let mut timing = None;
+        let mut machine_code_policy = None;
+        let mut unknown_policy = None;
+        let mut hash_interval = None;
+        let mut end = None;
+        let mut inputs = Vec::new();
+        let mut hashes = Vec::new();
+
+        for line in lines {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+
+            match fields.as_slice() {
+                [] => {},
+                ["rom", hash] => rom_hash = Some(parse_hex(hash)?),
+                ["boot", hash] => boot_hash = Some(parse_hex(hash)?),
+                ["base", addr] => base = Some(parse_hex(addr)? as usize),
+                ["quirks", quirks @ ..] => {
+                    for quirk in quirks {
+                        match quirk.split_once('=') {
+                            Some(("shift_lsb", value)) => shift_lsb = Some(value == "1"),
+                            Some(("stack", value)) => stack_depth = Some(parse_dec(value)? as usize),
+                            Some(("protect_font", value)) => protect_font = Some(value == "1"),
+                            _ => return Err(invalid("Unknown quirk in movie")),
+                        }
+                    }
+                },
+                ["rng", mode] => rng = Some(mode.to_string()),
+                ["seed", value] => seed = Some(parse_dec(value)?),
+                ["ipf", value] => ipf = Some(parse_dec(value)? as u32),
+                ["timing", name] => timing = Some(Timing::from_name(name).map_err(|_| invalid("Unknown timing model in movie"))?),
+                ["policies", policies @ ..] => {
+                    for policy in policies {
+                        let (kind, name) = policy.split_once('=').ok_or_else(|| invalid("Malformed policy in movie"))?;
+                        let value = Some(Policy::from_name(name).map_err(|_| invalid("Unknown policy in movie"))?);
+                        match kind {
+                            "machine_code"  => machine_code_policy = value,
+                            "unknown"       => unknown_policy = value,
+                            _               => return Err(invalid("Unknown policy in movie")),
+                        }
+                    }
+                },
+                ["interval", value] => hash_interval = Some(parse_dec(value)?),
+                ["k", frame, keys] => inputs.push((parse_dec(frame)?, parse_hex(keys)? as u16)),
+                ["h", frame, hash] => hashes.push((parse_dec(frame)?, parse_hex(hash)?)),
+                ["end", frame] => end = Some(parse_dec(frame)?),
+                _ => return Err(invalid("Malformed movie entry")),
+            }
+        }
+
+        let header = MovieHeader {
+            rom_hash: rom_hash.ok_or_else(|| invalid("Movie is missing the rom hash"))?,
+            boot_hash,
+            // movies from before the base was recorded loaded raw roms to the entry point
+            base: base.unwrap_or(ENTRY),
+            shift_lsb: shift_lsb.ok_or_else(|| invalid("Movie is missing the quirks"))?,
+            stack_depth: stack_depth.ok_or_else(|| invalid("Movie is missing the stack depth"))?,
+            // movies from before font protection was recorded ran with writable fonts
+            protect_font: protect_font.unwrap_or(false),
+            rng: rng.ok_or_else(|| invalid("Movie is missing the random number generator"))?,
+            seed: seed.ok_or_else(|| invalid("Movie is missing the seed"))?,
+            ipf: ipf.ok_or_else(|| invalid("Movie is missing the instructions per frame"))?,
+            // movies from before the VIP timing model ran a fixed number of instructions
+            timing: timing.unwrap_or(Timing::Ipf),
+            // movies from before the policies were recorded ran with the defaults of the commandline
+            machine_code_policy: machine_code_policy.unwrap_or(Policy::Ignore),
+            unknown_policy: unknown_policy.unwrap_or(Policy::Halt),
+            hash_interval: hash_interval.unwrap_or(0)
+        };
+
+        Ok(MoviePlayer {
+            header,
+            inputs,
+            hashes,
+            end: end.ok_or_else(|| invalid("Movie is truncated"))?,
+
+            next_input: 0,
+            next_hash: 0,
+            keypad: Keypad::new()
+        })
+    }
+
+    /**
+     *  @func   input       recorded keypad state for a frame
+     *
+     *  @param  frame       frame about to be executed
+     * */
+    pub fn input(&mut self, frame: u64) -> Keypad {
+        while self.next_input < self.inputs.len() && self.inputs[self.next_input].0 <= frame {
+            self.keypad.state = self.inputs[self.next_input].1;
+            self.next_input += 1;
+        }
+        self.keypad
+    }
+
+    /**
+     *  @func   check_state     compare the machine against the recorded state hash
+     *
+     *  @param  machine         machine after executing a frame
+     * */
+    pub fn check_state(&mut self, machine: &Machine) -> Result<(), io::Error> {
+        while self.next_hash < self.hashes.len() && self.hashes[self.next_hash].0 <= machine.frame {
+            let (frame, hash) = self.hashes[self.next_hash];
+            self.next_hash += 1;
+
+            if frame == machine.frame && hash != machine.state_hash() {
+                return Err(invalid(&format!("Movie desynced at frame {}", frame)));
+            }
+        }
+        Ok(())
+    }
+
+    /**
+     *  @func   done        check if all recorded frames were played
+     *
+     *  @param  frame       frames executed so far
+     * */
+    pub fn done(&self, frame: u64) -> bool {
+        frame >= self.end
+    }
+}
+
+/**
+ *  Input source of a session
+ *  - Off       keypad is driven by the host keyboard
+ *  - Record    keypad is driven by the host keyboard and written to a movie
+ *  - Play      keypad is driven by a movie, the host keyboard is ignored
+ * */
+pub enum Movie {
+    Off,
+    Record(MovieRecorder),
+    Play(MoviePlayer)
+}
+
+impl Movie {
+    /**
+     *  @func   before_frame    set the keypad for the next frame
+     *
+     *  @param  machine         machine about to execute a frame
+     *
+     *  @param  live            current state of the host keyboard
+     * */
+    pub fn before_frame(&mut self, machine: &mut Machine, live: Keypad) -> Result<(), io::Error> {
+        match self {
+            Movie::Off          => machine.keypad = live,
+            Movie::Record(rec)  => {
+                rec.record_input(machine.frame, live)?;
+                machine.keypad = live;
+            },
+            Movie::Play(player) => machine.keypad = player.input(machine.frame),
+        }
+        Ok(())
+    }
+
+    /**
+     *  @func   after_frame     record or verify the state hash
+     *
+     *  @param  machine         machine after executing a frame
+     * */
+    pub fn after_frame(&mut self, machine: &Machine) -> Result<(), io::Error> {
+        match self {
+            Movie::Off          => Ok(()),
+            Movie::Record(rec)  => rec.record_state(machine),
+            Movie::Play(player) => player.check_state(machine),
+        }
+    }
+
+    /**
+     *  @func   done        check if playback reached the end of the movie
+     *
+     *  @param  frame       frames executed so far
+     * */
+    pub fn done(&self, frame: u64) -> bool {
+        match self {
+            Movie::Play(player) => player.done(frame),
+            _                   => false,
+        }
+    }
+
+    /**
+     *  @func   finish      close the movie at the end of a session
+     *
+     *  @param  frame       frames executed in this session
+     * */
+    pub fn finish(self, frame: u64) -> Result<(), io::Error> {
+        match self {
+            Movie::Record(rec)  => rec.finish(frame),
+            _                   => Ok(()),
+        }
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn parse_dec(value: &str) -> Result<u64, io::Error> {
+    value.parse::<u64>().map_err(|_| invalid("Malformed number in movie"))
+}
+
+fn parse_hex(value: &str) -> Result<u64, io::Error> {
+    u64::from_str_radix(value, 16).map_err(|_| invalid("Malformed hex number in movie"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::path::PathBuf;
+    use std::process;
+    use super::super::super::Interpreter::opcode::Operations;
+    use super::super::super::Interpreter::rng::Rng;
+    use super::super::super::error::Chip8Error;
+
+    // 0x200: skip unless key 0 is up, 0x202: V0 += 1, 0x204: jump 0x200
+    const COUNTER: [u8; 6] = [0xE1, 0xA1, 0x70, 0x01, 0x12, 0x00];
+
+    // 0x200: call 0x200 until the stack overflows
+    const RECURSION: [u8; 2] = [0x22, 0x00];
+
+    fn header(image: &[u8]) -> MovieHeader {
+        MovieHeader {
+            rom_hash: MovieHeader::rom_hash(image),
+            boot_hash: None,
+            base: 0x200,
+            shift_lsb: true,
+            stack_depth: 12,
+            protect_font: false,
+            rng: "xorshift".to_string(),
+            seed: 1,
+            ipf: 10,
+            timing: Timing::Ipf,
+            machine_code_policy: Policy::Ignore,
+            unknown_policy: Policy::Halt,
+            hash_interval: 1
+        }
+    }
+
+    fn machine(image: &[u8], header: &MovieHeader) -> Machine {
+        let opcodes = Operations::new(header.shift_lsb, 0x050, header.machine_code_policy, header.unknown_policy);
+        let mut machine = Machine::new(opcodes, 0x200, Rng::new(&header.rng, header.seed).unwrap()).unwrap();
+        machine.mem.stack_depth = header.stack_depth;
+        machine.mem.load(image.to_vec(), 0x200).unwrap();
+        machine
+    }
+
+    // frames run the same way the commandline runs them, key 0 is held during frames 5 to 9
+    fn run(machine: &mut Machine, movie: &mut Movie, frames: u64) -> Result<(), Chip8Error> {
+        while !movie.done(machine.frame) && machine.frame < frames {
+            let keypad = Keypad { state: if (5..10).contains(&machine.frame) { 0x0001 } else { 0x0000 } };
+            movie.before_frame(machine, keypad)?;
+            machine.run_frame(10)?;
+            movie.after_frame(machine)?;
+        }
+        Ok(())
+    }
+
+    fn temp_movie(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("chip8-{}-{}.movie", name, process::id()))
+    }
+
+    #[test]
+    fn record_and_play_back() {
+        let path = temp_movie("round-trip");
+        let filename = path.to_str().unwrap();
+        let header = header(&COUNTER);
+
+        let mut recorded = machine(&COUNTER, &header);
+        let mut movie = Movie::Record(MovieRecorder::create(filename, &header).unwrap());
+        run(&mut recorded, &mut movie, 20).unwrap();
+        movie.finish(recorded.frame).unwrap();
+
+        let player = MoviePlayer::open(filename).unwrap();
+        assert_eq!(player.header, header);
+        player.header.verify(&COUNTER, 0x200, None).unwrap();
+        assert!(player.header.verify(&COUNTER, 0x300, None).is_err());
+
+        let mut played = machine(&COUNTER, &player.header);
+        let mut movie = Movie::Play(player);
+        run(&mut played, &mut movie, u64::MAX).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(played.frame, 20);
+        assert_ne!(played.reg.register_array[0], 0);
+        assert_eq!(played.state_hash(), recorded.state_hash());
+    }
+
+    #[test]
+    fn play_back_into_the_same_fault() {
+        let path = temp_movie("fault");
+        let filename = path.to_str().unwrap();
+        let header = header(&RECURSION);
+
+        let mut recorded = machine(&RECURSION, &header);
+        let mut movie = Movie::Record(MovieRecorder::create(filename, &header).unwrap());
+        let fault = run(&mut recorded, &mut movie, 10).unwrap_err();
+        assert!(fault.is_fault());
+        movie.finish(recorded.frame + 1).unwrap();
+
+        let mut played = machine(&RECURSION, &header);
+        let mut movie = Movie::Play(MoviePlayer::open(filename).unwrap());
+        let replayed = run(&mut played, &mut movie, u64::MAX).unwrap_err();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(replayed.to_string(), fault.to_string());
+        assert_eq!(played.frame, recorded.frame);
+    }
+
+    #[test]
+    fn truncated_movie() {
+        let path = temp_movie("truncated");
+        let filename = path.to_str().unwrap();
+        let header = header(&COUNTER);
+
+        // a recorder that is never finished leaves the end line out
+        let mut recorder = MovieRecorder::create(filename, &header).unwrap();
+        recorder.record_input(0, Keypad::new()).unwrap();
+        recorder.writer.flush().unwrap();
+
+        let err = MoviePlayer::open(filename).err().unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(err.to_string(), "Movie is truncated");
+    }
+}
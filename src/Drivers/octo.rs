@@ -0,0 +1,948 @@
+/*
+ *  ===========================================================
+ *
+ *     Filename:    octo.rs
+ *  Description:    assemble the Octo source stored in Octo
+ *                  cartridges
+ *
+ *  ===========================================================
+ *
+ *  Octo source is a stream of whitespace separated words, # starts a comment:
+ *
+ *      : main                  label, execution starts at main
+ *        i := digit            loads I with a label, forward references are fine
+ *        v0 := 5  v1 += v0     register operations
+ *        if v0 == 3 then clear skips are written as conditions
+ *        loop ... again        loops, left with while, if ... begin ... else ... end
+ *        sprite v0 v1 5        instructions
+ *        0xF0 0x90 0b1001      bytes, anything else is a subroutine call
+ *
+ *  Directives: :const, :alias, :unpack, :next, :org, :byte, :pointer, :call, :macro,
+ *  :calc and :assert. Calc expressions are evaluated from right to left without
+ *  precedence, as in Octo. :breakpoint, :monitor and :proto only matter to Octo's
+ *  debugger and are skipped. :stringmode is not supported.
+ *
+ *  Like Octo the program starts with a jump to main at 0x200, left out if main is
+ *  the first thing in the program.
+ * */
+
+use std::collections::{HashMap, VecDeque};
+use std::io;
+
+// address programs are assembled for
+const ORIGIN: usize = 0x200;
+
+// all of XO-CHIP's memory
+const MEMORY: usize = 0x10000;
+
+// words macros may expand to in total, a recursive macro would never stop
+const MAX_EXPANSION: usize = 1 << 18;
+
+// parentheses, unary and binary operators an expression may nest
+const MAX_NESTING: usize = 256;
+
+#[derive(Debug, Clone, PartialEq)]
+struct Token {
+    text: String,
+    line: usize
+}
+
+/**
+ *  Places that refer to a label defined later
+ *  - Addr      the low 12 bits of an instruction
+ *  - Long      16 bits, i := long and :pointer
+ *  - Unpack    the two 6XNN of :unpack, with the nibble put in front of the address
+ * */
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Fixup {
+    Addr,
+    Long,
+    Unpack(Option<u8>)
+}
+
+/**
+ *  Open control structures
+ *  - If        jump over the body of if ... begin
+ *  - Else      jump over the body of else
+ * */
+enum Block {
+    If(usize),
+    Else(usize)
+}
+
+struct Macro {
+    args: Vec<String>,
+    body: Vec<Token>,
+    calls: usize
+}
+
+struct Assembler {
+    tokens: VecDeque<Token>,
+    rom: Vec<u8>,
+    here: usize,
+    end: usize,
+    line: usize,
+
+    // labels and constants share the namespace, as in Octo
+    constants: HashMap<String, f64>,
+    aliases: HashMap<String, u8>,
+    macros: HashMap<String, Macro>,
+    fixups: Vec<(usize, String, Fixup, usize)>,
+
+    // words expanded from macros so far and the nesting of the expression being evaluated
+    expanded: usize,
+    nesting: usize,
+
+    blocks: Vec<Block>,
+    loops: Vec<(usize, Vec<usize>)>,
+
+    // the leading jump to main was left out
+    main_first: bool
+}
+
+/**
+ *  @func   assemble()  rom image of Octo source, loaded to 0x200
+ *
+ *  @param  source      program of an Octo cartridge
+ * */
+pub fn assemble(source: &str) -> Result<Vec<u8>, io::Error> {
+    let mut asm = Assembler {
+        tokens: tokenize(source),
+        rom: vec![0; MEMORY],
+        here: ORIGIN + 2,
+        end: ORIGIN + 2,
+        line: 0,
+
+        constants: HashMap::new(),
+        aliases: HashMap::new(),
+        macros: HashMap::new(),
+        fixups: Vec::new(),
+
+        expanded: 0,
+        nesting: 0,
+
+        blocks: Vec::new(),
+        loops: Vec::new(),
+
+        main_first: false
+    };
+
+    while let Some(token) = asm.next() {
+        asm.statement(token)?;
+    }
+    asm.finish()
+}
+
+/**
+ *  @func   tokenize()  words of the source with their line, strings stay one word
+ * */
+fn tokenize(source: &str) -> VecDeque<Token> {
+    let mut tokens = VecDeque::new();
+
+    for (n, line) in source.lines().enumerate() {
+        let mut rest = line;
+        loop {
+            rest = rest.trim_start();
+            if rest.is_empty() || rest.starts_with('#') {
+                break;
+            }
+            let length = if let Some(string) = rest.strip_prefix('"') {
+                string.find('"').map_or(rest.len(), |end| end + 2)
+            } else {
+                rest.find(char::is_whitespace).unwrap_or(rest.len())
+            };
+            tokens.push_back(Token { text: rest[..length].to_string(), line: n + 1 });
+            rest = &rest[length..];
+        }
+    }
+    tokens
+}
+
+impl Assembler {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.pop_front()?;
+        self.line = token.line;
+        Some(token)
+    }
+
+    fn error(&self, msg: &str) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, format!("Octo source line {}: {}", self.line, msg))
+    }
+
+    fn word(&mut self) -> Result<String, io::Error> {
+        self.next().map(|token| token.text).ok_or_else(|| self.error("unexpected end of the program"))
+    }
+
+    fn expect(&mut self, word: &str) -> Result<(), io::Error> {
+        match self.word()? {
+            found if found == word  => Ok(()),
+            found                   => Err(self.error(&format!("expected {}, found {}", word, found))),
+        }
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.front().map(|token| token.text.as_str())
+    }
+
+    /**
+     *  @func   statement() assemble a directive, an instruction or a byte
+     * */
+    fn statement(&mut self, token: Token) -> Result<(), io::Error> {
+        let word = token.text.as_str();
+
+        if let Some(reg) = self.register(word) {
+            return self.assignment(reg);
+        }
+
+        match word {
+            ":" => {
+                let name = self.word()?;
+                if name == "main" && self.here == ORIGIN + 2 && self.end == ORIGIN + 2 {
+                    self.here = ORIGIN;
+                    self.end = ORIGIN;
+                    self.main_first = true;
+                }
+                self.define(name, self.here as f64)
+            },
+            ":next" => {
+                let name = self.word()?;
+                self.define(name, (self.here + 1) as f64)
+            },
+            ":const" => {
+                let name = self.word()?;
+                let value = self.value()?;
+                self.define(name, value)
+            },
+            ":calc" => {
+                let name = self.word()?;
+                let value = self.calc()?;
+                self.define(name, value)
+            },
+            ":alias" => {
+                let name = self.word()?;
+                let reg = if self.peek() == Some("{") {
+                    self.calc()? as i64
+                } else {
+                    let word = self.word()?;
+                    self.register(&word).ok_or_else(|| self.error(&format!("{} is no register", word)))? as i64
+                };
+                if !(0..16).contains(&reg) {
+                    return Err(self.error("aliases name registers v0 to vf"));
+                }
+                self.aliases.insert(name, reg as u8);
+                Ok(())
+            },
+            ":unpack" => {
+                let nibble = match self.word()? {
+                    word if word == "long" => None,
+                    word => Some(self.number(&word)? as u8 & 0xF),
+                };
+                let name = self.word()?;
+                let at = self.here;
+                self.emit(&[0x60, 0x00, 0x61, 0x00])?;
+                self.refer(at, &name, Fixup::Unpack(nibble))
+            },
+            ":org" => {
+                let addr = self.value()? as i64;
+                if !(0..MEMORY as i64).contains(&addr) {
+                    return Err(self.error("address is outside of the memory"));
+                }
+                self.here = addr as usize;
+                Ok(())
+            },
+            ":byte" => {
+                let value = if self.peek() == Some("{") { self.calc()? } else { self.value()? };
+                let byte = self.byte(value as i64)?;
+                self.emit(&[byte])
+            },
+            ":pointer" => {
+                let name = self.word()?;
+                let at = self.here;
+                self.emit(&[0x00, 0x00])?;
+                self.refer(at, &name, Fixup::Long)
+            },
+            ":call" => {
+                let name = self.word()?;
+                self.jump(0x2000, &name)
+            },
+            ":macro" => self.define_macro(),
+            ":assert" => {
+                let msg = match self.peek() {
+                    Some(word) if word.starts_with('"') => self.word()?.trim_matches('"').to_string(),
+                    _ => "assertion failed".to_string(),
+                };
+                if self.calc()? == 0.0 {
+                    return Err(self.error(&msg));
+                }
+                Ok(())
+            },
+            ":breakpoint" | ":proto" => self.word().map(|_| ()),
+            ":monitor" => {
+                self.word()?;
+                self.word().map(|_| ())
+            },
+            ":stringmode" => Err(self.error(":stringmode isn't supported")),
+
+            ";" | "return"  => self.emit(&[0x00, 0xEE]),
+            "clear"         => self.emit(&[0x00, 0xE0]),
+            "hires"         => self.emit(&[0x00, 0xFF]),
+            "lores"         => self.emit(&[0x00, 0xFE]),
+            "exit"          => self.emit(&[0x00, 0xFD]),
+            "scroll-right"  => self.emit(&[0x00, 0xFB]),
+            "scroll-left"   => self.emit(&[0x00, 0xFC]),
+            "audio"         => self.emit(&[0xF0, 0x02]),
+            "scroll-down" | "scroll-up" => {
+                let rows = self.value()? as i64;
+                if !(0..16).contains(&rows) {
+                    return Err(self.error("scrolls by 0 to 15 rows"));
+                }
+                let opc = if word == "scroll-down" { 0x00C0 } else { 0x00D0 };
+                self.op(opc | rows as u16)
+            },
+            "plane" => {
+                let planes = self.value()? as i64;
+                if !(0..4).contains(&planes) {
+                    return Err(self.error("planes are 0 to 3"));
+                }
+                self.op(0xF001 | (planes as u16) << 8)
+            },
+            "bcd"       => self.reg_op(0xF033),
+            "saveflags" => self.reg_op(0xF075),
+            "loadflags" => self.reg_op(0xF085),
+            "save" | "load" => {
+                let x = self.reg_operand()?;
+                if self.peek() == Some("-") {
+                    self.next();
+                    let y = self.reg_operand()?;
+                    let opc = if word == "save" { 0x5002 } else { 0x5003 };
+                    return self.op(opc | (x as u16) << 8 | (y as u16) << 4);
+                }
+                let opc = if word == "save" { 0xF055 } else { 0xF065 };
+                self.op(opc | (x as u16) << 8)
+            },
+            "sprite" => {
+                let x = self.reg_operand()?;
+                let y = self.reg_operand()?;
+                let rows = self.value()? as i64;
+                if !(0..16).contains(&rows) {
+                    return Err(self.error("sprites are 0 to 15 rows high"));
+                }
+                self.op(0xD000 | (x as u16) << 8 | (y as u16) << 4 | rows as u16)
+            },
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let opc = match word { "delay" => 0xF015, "buzzer" => 0xF018, _ => 0xF03A };
+                self.reg_op(opc)
+            },
+            "jump" | "jump0" | "native" => {
+                let name = self.word()?;
+                let opc = match word { "jump" => 0x1000, "jump0" => 0xB000, _ => 0x0000 };
+                self.jump(opc, &name)
+            },
+            "i" => self.index(),
+
+            "if" => {
+                let (skip, body) = self.condition()?;
+                match self.word()?.as_str() {
+                    "then" => self.skip(skip, false),
+                    "begin" => {
+                        self.skip(skip, true)?;
+                        let at = self.here;
+                        self.emit(&[0x10, 0x00])?;
+                        self.blocks.push(Block::If(at));
+                        Ok(())
+                    },
+                    found => Err(self.error(&format!("expected then or begin after {}, found {}", body, found))),
+                }
+            },
+            "else" => match self.blocks.pop() {
+                Some(Block::If(at)) => {
+                    let jump = self.here;
+                    self.emit(&[0x10, 0x00])?;
+                    self.patch_jump(at, self.here);
+                    self.blocks.push(Block::Else(jump));
+                    Ok(())
+                },
+                _ => Err(self.error("else without if ... begin")),
+            },
+            "end" => match self.blocks.pop() {
+                Some(Block::If(at)) | Some(Block::Else(at)) => {
+                    self.patch_jump(at, self.here);
+                    Ok(())
+                },
+                None => Err(self.error("end without if ... begin")),
+            },
+            "loop" => {
+                self.loops.push((self.here, Vec::new()));
+                Ok(())
+            },
+            "while" => {
+                let (skip, _) = self.condition()?;
+                self.skip(skip, true)?;
+                let at = self.here;
+                self.emit(&[0x10, 0x00])?;
+                match self.loops.last_mut() {
+                    Some((_, breaks)) => { breaks.push(at); Ok(()) },
+                    None => Err(self.error("while outside of a loop")),
+                }
+            },
+            "again" => {
+                let (start, breaks) = self.loops.pop().ok_or_else(|| self.error("again without loop"))?;
+                self.op(0x1000 | start as u16 & 0xFFF)?;
+                for at in breaks {
+                    self.patch_jump(at, self.here);
+                }
+                Ok(())
+            },
+
+            _ if self.macros.contains_key(word) => self.expand(word),
+            _ => match parse_number(word) {
+                Some(value) => {
+                    let byte = self.byte(value)?;
+                    self.emit(&[byte])
+                },
+                // anything else calls a subroutine, which may be defined later
+                None if is_name(word) => self.jump(0x2000, word),
+                None => Err(self.error(&format!("unknown word {}", word))),
+            },
+        }
+    }
+
+    /**
+     *  @func   assignment()    vx := ..., vx += ... and the other register operations
+     * */
+    fn assignment(&mut self, x: u8) -> Result<(), io::Error> {
+        let op = self.word()?;
+        let x = (x as u16) << 8;
+        let rhs = self.word()?;
+
+        // register to register operations, 8XYN
+        if let Some(y) = self.register(&rhs) {
+            let n = match op.as_str() {
+                ":="    => 0x0,
+                "|="    => 0x1,
+                "&="    => 0x2,
+                "^="    => 0x3,
+                "+="    => 0x4,
+                "-="    => 0x5,
+                ">>="   => 0x6,
+                "=-"    => 0x7,
+                "<<="   => 0xE,
+                _       => return Err(self.error(&format!("unknown operator {}", op))),
+            };
+            return self.op(0x8000 | x | (y as u16) << 4 | n);
+        }
+
+        match (op.as_str(), rhs.as_str()) {
+            (":=", "key")       => self.op(0xF00A | x),
+            (":=", "delay")     => self.op(0xF007 | x),
+            (":=", "random")    => {
+                let mask = self.value()? as i64;
+                let mask = self.byte(mask)?;
+                self.op(0xC000 | x | mask as u16)
+            },
+            (":=", _) | ("+=", _) | ("-=", _) => {
+                let value = self.resolve(&rhs)? as i64;
+                let value = self.byte(if op == "-=" { -value } else { value })?;
+                let opc = if op == ":=" { 0x6000 } else { 0x7000 };
+                self.op(opc | x | value as u16)
+            },
+            _ => Err(self.error(&format!("{} needs a register, found {}", op, rhs))),
+        }
+    }
+
+    /**
+     *  @func   index()     i := ..., i += vx
+     * */
+    fn index(&mut self) -> Result<(), io::Error> {
+        let op = self.word()?;
+        let rhs = self.word()?;
+
+        match (op.as_str(), rhs.as_str()) {
+            ("+=", _) => {
+                let x = self.register(&rhs).ok_or_else(|| self.error("i += needs a register"))?;
+                self.op(0xF01E | (x as u16) << 8)
+            },
+            (":=", "hex")       => self.reg_op(0xF029),
+            (":=", "bighex")    => self.reg_op(0xF030),
+            (":=", "long")      => {
+                let name = self.word()?;
+                let at = self.here;
+                self.emit(&[0xF0, 0x00, 0x00, 0x00])?;
+                self.refer(at + 2, &name, Fixup::Long)
+            },
+            (":=", _)           => self.jump(0xA000, &rhs),
+            _                   => Err(self.error(&format!("unknown operator i {}", op))),
+        }
+    }
+
+    /**
+     *  @func   condition() parse a condition of if or while
+     *
+     *  returns the skips that test it and the words for error messages
+     * */
+    fn condition(&mut self) -> Result<(Skip, String), io::Error> {
+        let lhs = self.word()?;
+        let x = self.register(&lhs).ok_or_else(|| self.error(&format!("conditions start with a register, found {}", lhs)))?;
+        let op = self.word()?;
+
+        if op == "key" || op == "-key" {
+            return Ok((Skip::Key(x, op == "key"), format!("{} {}", lhs, op)));
+        }
+
+        let rhs = self.word()?;
+        let y = match self.register(&rhs) {
+            Some(y) => Operand::Register(y),
+            None => {
+                let value = self.resolve(&rhs)? as i64;
+                Operand::Byte(self.byte(value)?)
+            },
+        };
+
+        let skip = match op.as_str() {
+            "=="    => Skip::Equal(x, y, true),
+            "!="    => Skip::Equal(x, y, false),
+            ">"     => Skip::Compare(x, y, false, false),
+            "<="    => Skip::Compare(x, y, false, true),
+            "<"     => Skip::Compare(x, y, true, false),
+            ">="    => Skip::Compare(x, y, true, true),
+            _       => return Err(self.error(&format!("unknown comparison {}", op))),
+        };
+        Ok((skip, format!("{} {} {}", lhs, op, rhs)))
+    }
+
+    /**
+     *  @func   skip()      skip the next instruction if the condition is met (when) or not
+     * */
+    fn skip(&mut self, skip: Skip, when: bool) -> Result<(), io::Error> {
+        let (x, y) = match skip {
+            Skip::Key(x, pressed) => {
+                let opc = if pressed == when { 0xE09E } else { 0xE0A1 };
+                return self.op(opc | (x as u16) << 8);
+            },
+            Skip::Equal(x, y, equal) => (x as u16, (y, equal == when)),
+            Skip::Compare(x, y, a_minus_b, flag) => {
+                // VF := B, then VF := B - A (8XY5) or A - B (8XY7), VF ends up as the no borrow flag
+                match y {
+                    Operand::Register(y) => self.op(0x8F00 | (y as u16) << 4)?,
+                    Operand::Byte(value) => self.op(0x6F00 | value as u16)?,
+                }
+                let n = if a_minus_b { 0x7 } else { 0x5 };
+                self.op(0x8F00 | (x as u16) << 4 | n)?;
+                (0xF, (Operand::Byte(1), flag == when))
+            },
+        };
+
+        match y {
+            (Operand::Register(y), true)    => self.op(0x5000 | x << 8 | (y as u16) << 4),
+            (Operand::Register(y), false)   => self.op(0x9000 | x << 8 | (y as u16) << 4),
+            (Operand::Byte(value), true)    => self.op(0x3000 | x << 8 | value as u16),
+            (Operand::Byte(value), false)   => self.op(0x4000 | x << 8 | value as u16),
+        }
+    }
+
+    /**
+     *  @func   define_macro()  :macro name args { body }
+     * */
+    fn define_macro(&mut self) -> Result<(), io::Error> {
+        let name = self.word()?;
+        let mut args = Vec::new();
+        loop {
+            match self.word()? {
+                word if word == "{" => break,
+                word => args.push(word),
+            }
+        }
+
+        let mut body = Vec::new();
+        let mut depth = 1;
+        while depth > 0 {
+            let token = self.next().ok_or_else(|| self.error(&format!("macro {} isn't closed", name)))?;
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" => depth -= 1,
+                _ => {},
+            }
+            if depth > 0 {
+                body.push(token);
+            }
+        }
+        self.macros.insert(name, Macro { args, body, calls: 0 });
+        Ok(())
+    }
+
+    /**
+     *  @func   expand()    replace a macro call by its body, CALLS counts the calls so far
+     * */
+    fn expand(&mut self, name: &str) -> Result<(), io::Error> {
+        let count = self.macros[name].args.len();
+        let mut values = Vec::new();
+        for _ in 0..count {
+            values.push(self.word()?);
+        }
+
+        self.expanded += self.macros[name].body.len();
+        if self.expanded > MAX_EXPANSION {
+            return Err(self.error(&format!("macros expand to more than {} words, {} may call itself", MAX_EXPANSION, name)));
+        }
+
+        let line = self.line;
+        let mac = self.macros.get_mut(name).unwrap();
+        let body: Vec<Token> = mac.body.iter().map(|token| {
+            let text = match mac.args.iter().position(|arg| *arg == token.text) {
+                Some(n) => values[n].clone(),
+                None if token.text == "CALLS" => mac.calls.to_string(),
+                None => token.text.clone(),
+            };
+            Token { text, line }
+        }).collect();
+        mac.calls += 1;
+
+        for token in body.into_iter().rev() {
+            self.tokens.push_front(token);
+        }
+        Ok(())
+    }
+
+    /**
+     *  @func   calc()      evaluate { expression }
+     * */
+    fn calc(&mut self) -> Result<f64, io::Error> {
+        self.expect("{")?;
+        let value = self.expression()?;
+        self.expect("}")?;
+        Ok(value)
+    }
+
+    fn expression(&mut self) -> Result<f64, io::Error> {
+        self.nested(Assembler::binary)
+    }
+
+    fn term(&mut self) -> Result<f64, io::Error> {
+        self.nested(Assembler::operand)
+    }
+
+    // every level of an expression passes here, so untrusted source can't nest deeper than the stack
+    fn nested(&mut self, parse: fn(&mut Assembler) -> Result<f64, io::Error>) -> Result<f64, io::Error> {
+        if self.nesting >= MAX_NESTING {
+            return Err(self.error(&format!("expression nests deeper than {} levels", MAX_NESTING)));
+        }
+        self.nesting += 1;
+        let value = parse(self);
+        self.nesting -= 1;
+        value
+    }
+
+    // binary operators all bind alike and are evaluated from the right
+    fn binary(&mut self) -> Result<f64, io::Error> {
+        let lhs = self.term()?;
+        let op = match self.peek() {
+            Some(op) if BINARY.contains(&op) => op.to_string(),
+            _ => return Ok(lhs),
+        };
+        self.next();
+        let rhs = self.expression()?;
+
+        Ok(match op.as_str() {
+            "+"     => lhs + rhs,
+            "-"     => lhs - rhs,
+            "*"     => lhs * rhs,
+            "/"     => lhs / rhs,
+            "%"     => lhs % rhs,
+            "&"     => (lhs as i64 & rhs as i64) as f64,
+            "|"     => (lhs as i64 | rhs as i64) as f64,
+            "^"     => (lhs as i64 ^ rhs as i64) as f64,
+            "<<"    => ((lhs as i64) << (rhs as i64 & 63)) as f64,
+            ">>"    => ((lhs as i64) >> (rhs as i64 & 63)) as f64,
+            "pow"   => lhs.powf(rhs),
+            "min"   => lhs.min(rhs),
+            "max"   => lhs.max(rhs),
+            "<"     => (lhs < rhs) as u8 as f64,
+            ">"     => (lhs > rhs) as u8 as f64,
+            "<="    => (lhs <= rhs) as u8 as f64,
+            ">="    => (lhs >= rhs) as u8 as f64,
+            "=="    => (lhs == rhs) as u8 as f64,
+            _       => (lhs != rhs) as u8 as f64,
+        })
+    }
+
+    fn operand(&mut self) -> Result<f64, io::Error> {
+        let word = self.word()?;
+
+        if word == "(" {
+            let value = self.expression()?;
+            self.expect(")")?;
+            return Ok(value);
+        }
+        if UNARY.contains(&word.as_str()) {
+            let value = self.term()?;
+            return Ok(match word.as_str() {
+                "-"     => -value,
+                "~"     => !(value as i64) as f64,
+                "!"     => (value == 0.0) as u8 as f64,
+                "sin"   => value.sin(),
+                "cos"   => value.cos(),
+                "tan"   => value.tan(),
+                "exp"   => value.exp(),
+                "log"   => value.ln(),
+                "abs"   => value.abs(),
+                "sqrt"  => value.sqrt(),
+                "sign"  => value.signum(),
+                "ceil"  => value.ceil(),
+                "floor" => value.floor(),
+                _       => self.rom[value as usize % MEMORY] as f64,
+            });
+        }
+
+        match word.as_str() {
+            "HERE"  => Ok(self.here as f64),
+            "PI"    => Ok(std::f64::consts::PI),
+            "E"     => Ok(std::f64::consts::E),
+            _       => self.resolve(&word),
+        }
+    }
+
+    /**
+     *  @func   value()     next word as a number or constant
+     * */
+    fn value(&mut self) -> Result<f64, io::Error> {
+        let word = self.word()?;
+        self.resolve(&word)
+    }
+
+    fn resolve(&self, word: &str) -> Result<f64, io::Error> {
+        parse_number(word).map(|value| value as f64)
+            .or_else(|| self.constants.get(word).copied())
+            .ok_or_else(|| self.error(&format!("{} is undefined", word)))
+    }
+
+    fn number(&self, word: &str) -> Result<i64, io::Error> {
+        self.resolve(word).map(|value| value as i64)
+    }
+
+    fn byte(&self, value: i64) -> Result<u8, io::Error> {
+        if !(-128..256).contains(&value) {
+            return Err(self.error(&format!("{} doesn't fit into a byte", value)));
+        }
+        Ok(value as u8)
+    }
+
+    fn register(&self, word: &str) -> Option<u8> {
+        if let Some(reg) = self.aliases.get(word) {
+            return Some(*reg);
+        }
+        let digit = word.strip_prefix('v').or_else(|| word.strip_prefix('V'))?;
+        if digit.len() != 1 {
+            return None;
+        }
+        u8::from_str_radix(digit, 16).ok()
+    }
+
+    fn reg_operand(&mut self) -> Result<u8, io::Error> {
+        let word = self.word()?;
+        self.register(&word).ok_or_else(|| self.error(&format!("{} is no register", word)))
+    }
+
+    // FX.. instructions
+    fn reg_op(&mut self, opc: u16) -> Result<(), io::Error> {
+        let x = self.reg_operand()?;
+        self.op(opc | (x as u16) << 8)
+    }
+
+    fn define(&mut self, name: String, value: f64) -> Result<(), io::Error> {
+        if self.constants.contains_key(&name) || self.register(&name).is_some() {
+            return Err(self.error(&format!("{} is already defined", name)));
+        }
+        self.constants.insert(name, value);
+        Ok(())
+    }
+
+    /**
+     *  @func   jump()      instruction with a 12 bit address, of a label defined now or later
+     * */
+    fn jump(&mut self, opc: u16, name: &str) -> Result<(), io::Error> {
+        let at = self.here;
+        self.op(opc)?;
+        self.refer(at, name, Fixup::Addr)
+    }
+
+    // resolve now if possible, otherwise once the program is read
+    fn refer(&mut self, at: usize, name: &str, fixup: Fixup) -> Result<(), io::Error> {
+        if let Ok(value) = self.resolve(name) {
+            return self.patch(at, value as i64, fixup, self.line);
+        }
+        if !is_name(name) {
+            return Err(self.error(&format!("{} is no address", name)));
+        }
+        self.fixups.push((at, name.to_string(), fixup, self.line));
+        Ok(())
+    }
+
+    fn patch(&mut self, at: usize, value: i64, fixup: Fixup, line: usize) -> Result<(), io::Error> {
+        let fits = |bits: u32| (0..1 << bits).contains(&value);
+        let value = value as usize;
+
+        match fixup {
+            Fixup::Addr if fits(12) => {
+                self.rom[at] |= (value >> 8) as u8;
+                self.rom[at + 1] = value as u8;
+            },
+            Fixup::Long if fits(16) => {
+                self.rom[at] = (value >> 8) as u8;
+                self.rom[at + 1] = value as u8;
+            },
+            Fixup::Unpack(Some(nibble)) if fits(12) => {
+                self.rom[at + 1] = nibble << 4 | (value >> 8) as u8;
+                self.rom[at + 3] = value as u8;
+            },
+            Fixup::Unpack(None) if fits(16) => {
+                self.rom[at + 1] = (value >> 8) as u8;
+                self.rom[at + 3] = value as u8;
+            },
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidData,
+                format!("Octo source line {}: address {:#x} is out of reach", line, value))),
+        }
+        Ok(())
+    }
+
+    fn patch_jump(&mut self, at: usize, target: usize) {
+        self.rom[at] = 0x10 | (target >> 8) as u8 & 0xF;
+        self.rom[at + 1] = target as u8;
+    }
+
+    fn op(&mut self, opc: u16) -> Result<(), io::Error> {
+        self.emit(&opc.to_be_bytes())
+    }
+
+    fn emit(&mut self, bytes: &[u8]) -> Result<(), io::Error> {
+        if self.here + bytes.len() > MEMORY {
+            return Err(self.error("program is larger than the memory"));
+        }
+        self.rom[self.here..self.here + bytes.len()].copy_from_slice(bytes);
+        self.here += bytes.len();
+        self.end = self.end.max(self.here);
+        Ok(())
+    }
+
+    /**
+     *  @func   finish()    resolve forward references and cut the image out of the memory
+     * */
+    fn finish(mut self) -> Result<Vec<u8>, io::Error> {
+        if !self.blocks.is_empty() || !self.loops.is_empty() {
+            return Err(self.error("if ... begin or loop isn't closed"));
+        }
+
+        for (at, name, fixup, line) in std::mem::take(&mut self.fixups) {
+            let value = self.constants.get(&name).copied().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData,
+                format!("Octo source line {}: {} is undefined", line, name)))?;
+            self.patch(at, value as i64, fixup, line)?;
+        }
+
+        let main = self.constants.get("main").copied()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Octo program has no main label"))? as usize;
+        if !self.main_first {
+            self.patch_jump(ORIGIN, main);
+        }
+        Ok(self.rom[ORIGIN..self.end].to_vec())
+    }
+}
+
+/**
+ *  Conditions of if and while
+ *  - Key       key in VX is pressed (true) or not
+ *  - Equal     VX equals the operand (true) or not
+ *  - Compare   VF is the no borrow flag of A - B (true) or B - A, the condition is met if it is set (true) or not
+ * */
+#[derive(Debug, Clone, Copy)]
+enum Skip {
+    Key(u8, bool),
+    Equal(u8, Operand, bool),
+    Compare(u8, Operand, bool, bool)
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Operand {
+    Register(u8),
+    Byte(u8)
+}
+
+const BINARY: [&str; 19] = ["+", "-", "*", "/", "%", "&", "|", "^", "<<", ">>", "pow", "min", "max", "<", ">", "<=", ">=", "==", "!="];
+const UNARY: [&str; 14] = ["-", "~", "!", "sin", "cos", "tan", "exp", "log", "abs", "sqrt", "sign", "ceil", "floor", "@"];
+
+/**
+ *  @func   parse_number()  decimal, 0x hex or 0b binary literal
+ * */
+fn parse_number(word: &str) -> Option<i64> {
+    let (negative, digits) = match word.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, word),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(binary) = digits.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2).ok()?
+    } else if !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()) {
+        digits.parse().ok()?
+    } else {
+        return None;
+    };
+    Some(if negative { -value } else { value })
+}
+
+// words that can name a label or constant
+fn is_name(word: &str) -> bool {
+    word.chars().next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && word.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn main_first_leaves_out_the_jump() {
+        assert_eq!(assemble(": main v0 := 5 loop again").unwrap(), [0x60, 0x05, 0x12, 0x02]);
+        assert!(assemble(": main").unwrap().is_empty());
+    }
+
+    #[test]
+    fn jump_to_main_and_forward_references() {
+        let image = assemble(": data 0xF0 0x90 : main i := data sprite v0 v1 2 jump end : end ;").unwrap();
+        assert_eq!(image, [0x12, 0x04, 0xF0, 0x90, 0xA2, 0x02, 0xD0, 0x12, 0x12, 0x0A, 0x00, 0xEE]);
+    }
+
+    #[test]
+    fn conditions_skip_the_body() {
+        assert_eq!(assemble(": main if v1 == 3 then v2 += v3").unwrap(), [0x41, 0x03, 0x82, 0x34]);
+        assert_eq!(assemble(": main if v1 key begin clear end").unwrap(), [0xE1, 0x9E, 0x12, 0x06, 0x00, 0xE0]);
+        assert_eq!(assemble(": main if v1 > v2 then v3 := 1").unwrap(), [0x8F, 0x20, 0x8F, 0x15, 0x3F, 0x01, 0x63, 0x01]);
+    }
+
+    #[test]
+    fn calc_is_evaluated_from_the_right() {
+        assert_eq!(assemble(":calc x { 2 * 3 + 1 } : main v0 := x").unwrap(), [0x60, 0x08]);
+    }
+
+    #[test]
+    fn recursive_macro() {
+        let err = assemble(":macro m { m } : main m").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "Octo source line 1: macros expand to more than 262144 words, m may call itself");
+    }
+
+    #[test]
+    fn deeply_nested_calc() {
+        let source = format!(":calc x {{ {} 1 {} }} : main", "( ".repeat(100_000), ") ".repeat(100_000));
+        let err = assemble(&source).unwrap_err();
+        assert_eq!(err.to_string(), "Octo source line 1: expression nests deeper than 256 levels");
+
+        let source = format!(":calc x {{ {} 1 }} : main", "- ".repeat(100_000));
+        assert!(assemble(&source).is_err());
+    }
+
+    #[test]
+    fn undefined_label() {
+        let err = assemble(": main\n  jump nowhere").unwrap_err();
+        assert_eq!(err.to_string(), "Octo source line 2: nowhere is undefined");
+    }
+}
@@ -0,0 +1,131 @@
+/*
+ *  ===========================================================
+ *
+ *     Filename:    palette.rs
+ *  Description:    colors the framebuffer is shown with and the
+ *                  built-in themes
+ *
+ *  ===========================================================
+ * */
+
+use std::io;
+
+use super::phosphor::LEVELS;
+
+pub type Rgb = [u8; 3];
+
+/**
+ *  Built-in themes, in the order the theme hotkey cycles through them
+ *  colors are off, on, second XO-CHIP plane and both planes
+ * */
+const THEMES: [(&str, [Rgb; 4]); 5] = [
+    ("classic",     [[0x00, 0x00, 0x00], [0xFF, 0xFF, 0xFF], [0xAA, 0xAA, 0xAA], [0x55, 0x55, 0x55]]),
+    ("amber",       [[0x1A, 0x0F, 0x00], [0xFF, 0xB0, 0x00], [0xB3, 0x7A, 0x00], [0x66, 0x44, 0x00]]),
+    ("green",       [[0x00, 0x14, 0x00], [0x33, 0xFF, 0x33], [0x1F, 0x9E, 0x1F], [0x0D, 0x4D, 0x0D]]),
+    ("lcd",         [[0x9B, 0xBC, 0x0F], [0x0F, 0x38, 0x0F], [0x30, 0x62, 0x30], [0x8B, 0xAC, 0x0F]]),
+    ("contrast",    [[0x00, 0x00, 0x00], [0xFF, 0xFF, 0x00], [0x00, 0xFF, 0xFF], [0xFF, 0xFF, 0xFF]]),
+];
+
+/**
+ *  Colors by pixel value, 0 is off and 1 is on, XO-CHIP's second plane adds 2 and 3
+ * */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Palette {
+    pub colors: [Rgb; 4],
+
+    // theme the colors started from, the hotkey continues with the one after it
+    theme: usize
+}
+
+impl Palette {
+    /**
+     *  @func   from_name()     built-in theme by its commandline name
+     *
+     *  @param  name            "classic", "amber", "green", "lcd" or "contrast"
+     * */
+    pub fn from_name(name: &str) -> Result<Palette, io::Error> {
+        THEMES.iter()
+            .position(|(theme, _)| *theme == name)
+            .map(Palette::theme)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Unknown theme"))
+    }
+
+    fn theme(theme: usize) -> Palette {
+        Palette {
+            colors: THEMES[theme].1,
+            theme
+        }
+    }
+
+    /**
+     *  @func   name()      name of the theme the colors started from
+     * */
+    pub fn name(&self) -> &'static str {
+        THEMES[self.theme].0
+    }
+
+    /**
+     *  @func   next()      the built-in theme after this one, wraps around
+     * */
+    pub fn next(&self) -> Palette {
+        Palette::theme((self.theme + 1) % THEMES.len())
+    }
+
+    /**
+     *  @func   set_colors()    replace colors of the theme
+     *
+     *  @param  spec            off,on[,plane 2[,both planes]] as hex RRGGBB, a leading # is optional
+     * */
+    pub fn set_colors(&mut self, spec: &str) -> Result<(), io::Error> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidInput, "Colors are given as off,on[,plane2[,both]] in hex RRGGBB");
+
+        let colors: Vec<&str> = spec.split(',').collect();
+        if colors.len() < 2 || colors.len() > self.colors.len() {
+            return Err(invalid());
+        }
+
+        for (color, hex) in self.colors.iter_mut().zip(colors) {
+            let hex = hex.trim().trim_start_matches('#');
+            if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(invalid());
+            }
+            let value = u32::from_str_radix(hex, 16).map_err(|_| invalid())?;
+            *color = [(value >> 16) as u8, (value >> 8) as u8, value as u8];
+        }
+        Ok(())
+    }
+
+    /**
+     *  @func   color()     color of a pixel value
+     * */
+    pub fn color(&self, pixel: u8) -> Rgb {
+        self.colors[(pixel & 3) as usize]
+    }
+
+    /**
+     *  @func   shade()     color of a shade, the pixel's color mixed into the off color by its brightness
+     * */
+    pub fn shade(&self, shade: u8) -> Rgb {
+        let (off, on) = (self.colors[0], self.color(shade / LEVELS));
+        let level = (shade % LEVELS) as u32;
+
+        let mut rgb = off;
+        for (channel, (off, on)) in rgb.iter_mut().zip(off.iter().zip(on.iter())) {
+            *channel = ((*off as u32 * (LEVELS as u32 - 1 - level) + *on as u32 * level) / (LEVELS as u32 - 1)) as u8;
+        }
+        rgb
+    }
+
+    /**
+     *  @func   shades()    colors of all 256 shades flattened to RGB bytes, for indexed formats
+     * */
+    pub fn shades(&self) -> Vec<u8> {
+        (0..=255).flat_map(|shade| self.shade(shade)).collect()
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Palette {
+        Palette::theme(0)
+    }
+}
@@ -0,0 +1,166 @@
+/*
+ *  ===========================================================
+ *
+ *     Filename:    phosphor.rs
+ *  Description:    blend consecutive frames against the flicker
+ *                  of sprites that are erased and redrawn
+ *
+ *  ===========================================================
+ * */
+
+use std::io;
+
+use super::display::{FBuffer, HORIZONTAL, VERTICAL};
+
+// brightness steps of a shade, the brightest is LEVELS - 1
+pub const LEVELS: u8 = 64;
+
+// longest fade, a minute at 60 frames per second
+const MAX_FADE: u64 = 3600;
+
+/**
+ *  Framebuffer as seen on screen, every shade is pixel value * LEVELS + brightness,
+ *  so unlit pixels are 0 and fully lit pixels have a brightness of LEVELS - 1
+ * */
+pub type Shades = [[u8; HORIZONTAL]; VERTICAL];
+
+/**
+ *  How the shown frame follows the framebuffer
+ *  - Off       every frame is shown as it is
+ *  - Fade      pixels fade out over the given number of frames after they went dark
+ *  - Max       a pixel is lit if it was lit in this or the last frame
+ * */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Blend {
+    Off,
+    Fade(u64),
+    Max
+}
+
+impl Blend {
+    /**
+     *  @func   from_name()     blend by its commandline name
+     *
+     *  @param  name            "off", "max" or the number of frames a pixel fades over, at most MAX_FADE
+     * */
+    pub fn from_name(name: &str) -> Result<Blend, io::Error> {
+        match name {
+            "off"   => Ok(Blend::Off),
+            "max"   => Ok(Blend::Max),
+            _       => match name.parse::<u64>() {
+                Ok(frames) if frames > 0 => Ok(Blend::Fade(frames.min(MAX_FADE))),
+                _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "Unknown phosphor blend")),
+            },
+        }
+    }
+}
+
+/**
+ *  Shades shown for the emulated frames, shared by the window, the terminal and all image outputs
+ * */
+pub struct Phosphor {
+    blend: Blend,
+
+    // framebuffer of the last frame
+    previous: FBuffer,
+
+    // value each pixel was last lit with and the frames since
+    lit: FBuffer,
+    age: [[u64; HORIZONTAL]; VERTICAL],
+
+    shades: Shades
+}
+
+impl Phosphor {
+    /**
+     *  @func   new         start with the framebuffer fully lit as it is
+     *
+     *  @param  blend       how frames are blended
+     *
+     *  @param  fbuffer     framebuffer before the first emulated frame
+     * */
+    pub fn new(blend: Blend, fbuffer: &FBuffer) -> Phosphor {
+        let mut phosphor = Phosphor {
+            blend,
+            previous: *fbuffer,
+            lit: *fbuffer,
+            age: [[0; HORIZONTAL]; VERTICAL],
+            shades: [[0; HORIZONTAL]; VERTICAL]
+        };
+
+        for (shades, line) in phosphor.shades.iter_mut().zip(fbuffer.iter()) {
+            for (shade, pixel) in shades.iter_mut().zip(line.iter()) {
+                *shade = shade_of(*pixel, LEVELS - 1);
+            }
+        }
+        phosphor
+    }
+
+    /**
+     *  @func   update      blend the framebuffer after an emulated frame
+     *
+     *  @param  fbuffer     framebuffer after the frame
+     * */
+    pub fn update(&mut self, fbuffer: &FBuffer) {
+        for (y, line) in fbuffer.iter().enumerate() {
+            for (x, &pixel) in line.iter().enumerate() {
+                if pixel != 0 {
+                    self.lit[y][x] = pixel;
+                    self.age[y][x] = 0;
+                } else {
+                    self.age[y][x] = self.age[y][x].saturating_add(1);
+                }
+
+                self.shades[y][x] = match self.blend {
+                    Blend::Off => shade_of(pixel, LEVELS - 1),
+                    Blend::Max => {
+                        let pixel = if pixel != 0 { pixel } else { self.previous[y][x] };
+                        shade_of(pixel, LEVELS - 1)
+                    },
+                    Blend::Fade(frames) => {
+                        let left = frames.saturating_sub(self.age[y][x]);
+                        shade_of(self.lit[y][x], ((LEVELS - 1) as u64 * left / frames) as u8)
+                    },
+                };
+            }
+        }
+        self.previous = *fbuffer;
+    }
+
+    /**
+     *  @func   shades()    shades of the last frame
+     * */
+    pub fn shades(&self) -> &Shades {
+        &self.shades
+    }
+}
+
+/**
+ *  @func   shade_of()  shade of a pixel value at a brightness
+ * */
+fn shade_of(pixel: u8, level: u8) -> u8 {
+    if pixel == 0 || level == 0 {
+        0
+    } else {
+        (pixel & 3) * LEVELS + level
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn long_fade_is_clamped() {
+        assert_eq!(Blend::from_name("18446744073709551615").unwrap(), Blend::Fade(MAX_FADE));
+
+        // a pixel that went dark keeps fading without overflowing
+        let mut fbuffer = [[0; HORIZONTAL]; VERTICAL];
+        fbuffer[0][0] = 1;
+        let mut phosphor = Phosphor::new(Blend::from_name("18446744073709551615").unwrap(), &fbuffer);
+        phosphor.update(&fbuffer);
+        fbuffer[0][0] = 0;
+        phosphor.update(&fbuffer);
+        assert_eq!(phosphor.shades()[0][0], shade_of(1, LEVELS - 2));
+    }
+}
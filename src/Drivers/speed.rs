@@ -0,0 +1,170 @@
+/*
+ *  ===========================================================
+ *
+ *     Filename:    speed.rs
+ *  Description:    pause, frame advance, fast forward and slow
+ *                  motion of the emulated frames
+ *
+ *  ===========================================================
+ * */
+
+use std::io;
+
+// host frames per second, the rate the timers of the machine tick at
+pub const RATE: u64 = 60;
+
+// emulated frames per host frame as a fraction, None runs as many as fit into a host frame
+const LADDER: [Option<(u64, u64)>; 7] = [Some((1, 4)), Some((1, 2)), Some((1, 1)), Some((2, 1)), Some((4, 1)), Some((8, 1)), None];
+const NORMAL: usize = 2;
+
+/**
+ *  Emulated frames to run in a host frame
+ *  - Count     exactly this many, 0 while paused or between frames in slow motion
+ *  - Uncapped  as many as the host manages
+ * */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Frames {
+    Count(u64),
+    Uncapped
+}
+
+/**
+ *  Speed of the emulation, changed by the frontends at runtime
+ *
+ *  The timers tick with the emulated frames, so the game runs the same at any speed.
+ * */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Speed {
+    pub paused: bool,
+
+    // run a single frame although paused
+    advance: bool,
+
+    // position in LADDER and the fraction of a frame owed from slow motion
+    step: usize,
+    credit: u64,
+
+    // instructions per frame, only adjustable if no movie depends on them
+    pub ipf: u32,
+    adjustable: bool
+}
+
+impl Speed {
+    /**
+     *  @func   new         start at normal speed
+     *
+     *  @param  ipf         instructions executed per frame
+     *
+     *  @param  adjustable  ipf may be changed at runtime
+     * */
+    pub fn new(ipf: u32, adjustable: bool) -> Speed {
+        Speed {
+            paused: false,
+            advance: false,
+            step: NORMAL,
+            credit: 0,
+            ipf,
+            adjustable
+        }
+    }
+
+    /**
+     *  @func   set_rate()  speed by its commandline name
+     *
+     *  @param  name        "1/4", "1/2", "1", "2", "4", "8" or "max"
+     * */
+    pub fn set_rate(&mut self, name: &str) -> Result<(), io::Error> {
+        self.step = LADDER.iter()
+            .position(|rate| rate_name(*rate) == name)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Unknown speed"))?;
+        Ok(())
+    }
+
+    /**
+     *  @func   pause()     pause or resume
+     * */
+    pub fn pause(&mut self) {
+        self.paused = !self.paused;
+    }
+
+    /**
+     *  @func   advance()   pause and run a single frame
+     * */
+    pub fn advance(&mut self) {
+        self.paused = true;
+        self.advance = true;
+    }
+
+    /**
+     *  @func   faster()    next speed up the ladder, up to uncapped
+     * */
+    pub fn faster(&mut self) {
+        self.step = (self.step + 1).min(LADDER.len() - 1);
+    }
+
+    /**
+     *  @func   slower()    next speed down the ladder, down to 1/4
+     * */
+    pub fn slower(&mut self) {
+        self.step = self.step.saturating_sub(1);
+    }
+
+    /**
+     *  @func   more_ipf()  raise the instructions per frame by about a tenth
+     * */
+    pub fn more_ipf(&mut self) {
+        if self.adjustable {
+            self.ipf = self.ipf.saturating_add((self.ipf / 10).max(1));
+        }
+    }
+
+    /**
+     *  @func   less_ipf()  lower the instructions per frame by about a tenth, to at least 1
+     * */
+    pub fn less_ipf(&mut self) {
+        if self.adjustable {
+            self.ipf = self.ipf.saturating_sub((self.ipf / 10).max(1)).max(1);
+        }
+    }
+
+    /**
+     *  @func   frames()    emulated frames to run in the host frame that is due
+     * */
+    pub fn frames(&mut self) -> Frames {
+        if self.paused {
+            let frames = self.advance as u64;
+            self.advance = false;
+            return Frames::Count(frames);
+        }
+
+        match LADDER[self.step] {
+            Some((frames, host_frames)) => {
+                self.credit += frames;
+                let count = self.credit / host_frames;
+                self.credit %= host_frames;
+                Frames::Count(count)
+            },
+            None => Frames::Uncapped,
+        }
+    }
+
+    /**
+     *  @func   status()    state for a window title or status line, e.g. "1/2x ipf 10"
+     * */
+    pub fn status(&self) -> String {
+        let rate = match LADDER[self.step] {
+            _ if self.paused    => "paused".to_string(),
+            Some(rate)          => format!("{}x", rate_name(Some(rate))),
+            None                => rate_name(None),
+        };
+        format!("{} ipf {}", rate, self.ipf)
+    }
+}
+
+fn rate_name(rate: Option<(u64, u64)>) -> String {
+    match rate {
+        Some((frames, 1))           => frames.to_string(),
+        Some((frames, host_frames)) => format!("{}/{}", frames, host_frames),
+        None                        => "max".to_string(),
+    }
+}
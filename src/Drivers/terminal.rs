@@ -0,0 +1,354 @@
+/*
+ *  ===========================================================
+ *
+ *     Filename:    terminal.rs
+ *  Description:    draw the framebuffer in a plain terminal and
+ *                  read the keypad from raw mode key presses
+ *
+ *  ===========================================================
+ * */
+
+extern crate crossterm;
+
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags};
+use crossterm::style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor};
+use crossterm::{cursor, queue, terminal};
+use std::io;
+use std::io::prelude::*;
+use std::time::{Duration, Instant};
+
+use super::display::{HORIZONTAL, VERTICAL};
+use super::frontend::Frontend;
+use super::keyboard_io::{KeyMap, Keypad};
+use super::palette::{Palette, Rgb};
+use super::phosphor::{Shades, LEVELS};
+use super::speed::Speed;
+
+// most terminals only report presses, a key stays held this long after its last press or repeat
+const HOLD: Duration = Duration::from_millis(200);
+
+/**
+ *  Characters the framebuffer is drawn with
+ *  - HalfBlock     one cell per 1x2 pixels, upper half block with fore- and background colour
+ *  - Braille       one cell per 2x4 pixels, braille dots
+ * */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Cells {
+    HalfBlock,
+    Braille
+}
+
+impl Cells {
+    /**
+     *  @func   from_name()     cell type by its commandline name
+     *
+     *  @param  name            "half" or "braille"
+     * */
+    pub fn from_name(name: &str) -> Result<Cells, io::Error> {
+        match name {
+            "half"      => Ok(Cells::HalfBlock),
+            "braille"   => Ok(Cells::Braille),
+            _           => Err(io::Error::new(io::ErrorKind::InvalidInput, "Unknown terminal cells")),
+        }
+    }
+
+    /**
+     *  @func   height()    pixel rows covered by a single cell
+     * */
+    fn height(self) -> usize {
+        match self {
+            Cells::HalfBlock    => 2,
+            Cells::Braille      => 4,
+        }
+    }
+}
+
+/**
+ *  Terminal frontend, an alternative to Display for machines without a window system
+ *
+ *  The terminal is put into raw mode on the alternate screen and restored
+ *  when the Terminal is dropped, even if the machine faults.
+ * */
+pub struct Terminal {
+    out: io::Stdout,
+    cells: Cells,
+    palette: Palette,
+
+    // escape sequences last written per cell row, rows are only redrawn if they change
+    rows: Vec<Vec<u8>>,
+
+    // shades presented since the last draw and the speed shown below the help line
+    pending: Option<Shades>,
+    status: String,
+
+    // the terminal reports key releases (kitty keyboard protocol)
+    releases: bool,
+
+    // time each key was last pressed at, held keys are released by the clock, not by emulated frames
+    pressed: [Option<Instant>; 16],
+
+    // arrow keys, space and enter on top of the keypad layout
+    keys: KeyMap,
+
+    // the buzzer was on after the last frame
+    buzzing: bool
+}
+
+impl Terminal {
+    /**
+     *  @func   open        switch the terminal to raw mode and the alternate screen
+     *
+     *  @param  cells       characters the framebuffer is drawn with
+     *
+     *  @param  palette     colors the framebuffer is drawn with, F9 switches to the next theme
+     *
+     *  @param  keys        keypad keys of the arrow keys, space and enter
+     */
+    pub fn open(cells: Cells, palette: Palette, keys: KeyMap) -> Result<Terminal, io::Error> {
+        terminal::enable_raw_mode()?;
+
+        let mut term = Terminal {
+            out: io::stdout(),
+            cells,
+            palette,
+            rows: Vec::new(),
+            pending: None,
+            status: String::new(),
+            releases: terminal::supports_keyboard_enhancement().unwrap_or(false),
+            pressed: [None; 16],
+            keys,
+            buzzing: false
+        };
+
+        queue!(term.out, terminal::EnterAlternateScreen, cursor::Hide)?;
+        if term.releases {
+            queue!(term.out, event::PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES))?;
+        }
+        term.clear()?;
+
+        Ok(term)
+    }
+
+    /**
+     *  @func   clear       clear the screen and draw the help and status lines below the framebuffer
+     */
+    fn clear(&mut self) -> Result<(), io::Error> {
+        let height = self.cells.height();
+
+        self.rows.clear();
+        queue!(self.out,
+            terminal::Clear(terminal::ClearType::All),
+            cursor::MoveTo(0, (VERTICAL / height) as u16),
+            Print("Esc quits, F9 theme, p pause, n step, [ ] speed, - = ipf"))?;
+        self.show_status()
+    }
+
+    /**
+     *  @func   show_status draw the status line
+     */
+    fn show_status(&mut self) -> Result<(), io::Error> {
+        let row = VERTICAL / self.cells.height() + 1;
+
+        queue!(self.out,
+            cursor::MoveTo(0, row as u16),
+            terminal::Clear(terminal::ClearType::CurrentLine),
+            Print(&self.status))?;
+        self.out.flush()
+    }
+
+    /**
+     *  @func   handle      apply a terminal event to the keypad
+     *
+     *  returns false once the user asked to quit
+     */
+    fn handle(&mut self, event: Event, keypad: &mut Keypad, speed: &mut Speed) -> Result<bool, io::Error> {
+        match event {
+            Event::Key(key) => {
+                let quit = key.code == KeyCode::Esc
+                    || (key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL));
+                if quit {
+                    return Ok(false);
+                }
+
+                // redraw everything in the colors of the next theme
+                if key.code == KeyCode::F(9) && key.kind != KeyEventKind::Release {
+                    self.palette = self.palette.next();
+                    self.rows.clear();
+                }
+
+                if key.kind != KeyEventKind::Release {
+                    match key.code {
+                        KeyCode::Char('p') => speed.pause(),
+                        KeyCode::Char('n') => speed.advance(),
+                        KeyCode::Char('[') => speed.slower(),
+                        KeyCode::Char(']') => speed.faster(),
+                        KeyCode::Char('-') => speed.less_ipf(),
+                        KeyCode::Char('=') => speed.more_ipf(),
+                        _ => {},
+                    }
+                }
+
+                if let Some(k) = self.keys.map_code(key.code) {
+                    if key.kind == KeyEventKind::Release {
+                        keypad.release(k);
+                        self.pressed[k as usize] = None;
+                    } else {
+                        keypad.press(k);
+                        self.pressed[k as usize] = Some(Instant::now());
+                    }
+                }
+            },
+            Event::Resize(..) => self.clear()?,
+            _ => {},
+        }
+        Ok(true)
+    }
+
+    /**
+     *  @func   release     release keys that weren't pressed again within HOLD
+     */
+    fn release(&mut self, keypad: &mut Keypad) {
+        if self.releases {
+            return;
+        }
+
+        for (k, pressed) in self.pressed.iter_mut().enumerate() {
+            if pressed.is_some_and(|at| at.elapsed() >= HOLD) {
+                keypad.release(k as u8);
+                *pressed = None;
+            }
+        }
+    }
+
+    /**
+     *  @func   draw        redraw the cell rows whose pixels changed
+     *
+     *  @param  shades      shades of the framebuffer to be drawn
+     */
+    fn draw(&mut self, shades: &Shades) -> Result<(), io::Error> {
+        let height = self.cells.height();
+
+        for row in 0..VERTICAL / height {
+            let line = self.render_row(shades, row)?;
+
+            if self.rows.get(row) != Some(&line) {
+                queue!(self.out, cursor::MoveTo(0, row as u16))?;
+                self.out.write_all(&line)?;
+
+                if row < self.rows.len() {
+                    self.rows[row] = line;
+                } else {
+                    self.rows.push(line);
+                }
+            }
+        }
+        self.out.flush()
+    }
+
+    /**
+     *  @func   render_row  escape sequences and characters of a single cell row
+     *
+     *  @param  row         cell row, covers the pixel rows row * cell height onwards
+     */
+    fn render_row(&self, shades: &Shades, row: usize) -> Result<Vec<u8>, io::Error> {
+        let mut line = Vec::new();
+
+        match self.cells {
+            Cells::HalfBlock => {
+                let mut colors = None;
+
+                for (top, bottom) in shades[2 * row].iter().zip(shades[2 * row + 1].iter()) {
+                    let top = to_color(self.palette.shade(*top));
+                    let bottom = to_color(self.palette.shade(*bottom));
+
+                    if colors != Some((top, bottom)) {
+                        queue!(line, SetForegroundColor(top), SetBackgroundColor(bottom))?;
+                        colors = Some((top, bottom));
+                    }
+                    queue!(line, Print('\u{2580}'))?;
+                }
+            },
+            Cells::Braille => {
+                // dot bits of the 2x4 cell, by row and column
+                const DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+                // dots can't have colors of their own, pixels at least half lit are drawn in the on color
+                let (on, off) = (to_color(self.palette.color(1)), to_color(self.palette.color(0)));
+                queue!(line, SetForegroundColor(on), SetBackgroundColor(off))?;
+                for cell in 0..HORIZONTAL / 2 {
+                    let mut bits = 0;
+
+                    for (dy, dots) in DOTS.iter().enumerate() {
+                        for (dx, dot) in dots.iter().enumerate() {
+                            if shades[4 * row + dy][2 * cell + dx] % LEVELS >= LEVELS / 2 {
+                                bits |= dot;
+                            }
+                        }
+                    }
+                    queue!(line, Print(char::from_u32(0x2800 + bits).unwrap_or(' ')))?;
+                }
+            },
+        }
+
+        queue!(line, ResetColor)?;
+        Ok(line)
+    }
+}
+
+/**
+ *  @func   to_color()  convert a palette color to a true color escape
+ */
+fn to_color(rgb: Rgb) -> Color {
+    Color::Rgb { r: rgb[0], g: rgb[1], b: rgb[2] }
+}
+
+/**
+ *  Runs the machine at 60 frames per second until Esc is pressed
+ * */
+impl Frontend for Terminal {
+    fn poll(&mut self, keypad: &mut Keypad, speed: &mut Speed, _frame: u64) -> Result<bool, io::Error> {
+        // only the last of the frames run since the last poll is drawn
+        if let Some(shades) = self.pending.take() {
+            self.draw(&shades)?;
+        }
+        if self.status != speed.status() {
+            self.status = speed.status();
+            self.show_status()?;
+        }
+
+        // the machine loop waits for the frames, only take the input that arrived meanwhile
+        while event::poll(Duration::ZERO)? {
+            if !self.handle(event::read()?, keypad, speed)? {
+                return Ok(false);
+            }
+        }
+
+        self.release(keypad);
+        Ok(true)
+    }
+
+    fn present(&mut self, shades: &Shades, _frame: u64) -> Result<(), io::Error> {
+        self.pending = Some(*shades);
+        Ok(())
+    }
+
+    // the terminal bell rings once whenever the buzzer starts
+    fn sound(&mut self, on: bool) {
+        if on && !self.buzzing {
+            let _ = queue!(self.out, Print('\u{7}'));
+        }
+        self.buzzing = on;
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        // best effort, there is nobody left to report a failure to
+        if self.releases {
+            let _ = queue!(self.out, event::PopKeyboardEnhancementFlags);
+        }
+        let _ = queue!(self.out, cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = self.out.flush();
+        let _ = terminal::disable_raw_mode();
+    }
+}
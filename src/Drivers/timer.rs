@@ -1,18 +1,97 @@
-use std::time::Instant;
+/*
+ *  ===========================================================
+ *
+ *     Filename:    timer.rs
+ *  Description:    paces the machine loop to a fixed rate of
+ *                  host frames without drifting
+ *
+ *  ===========================================================
+ * */
 
-pub struct Timer(u128);
+use std::thread;
+use std::time::{Duration, Instant};
+
+// the last part of a wait is spun, sleeping is only accurate to about a millisecond
+const SPIN: Duration = Duration::from_millis(1);
+
+// frames the timer catches up on at most, a longer stall (suspended host, debugger) is dropped
+const MAX_BEHIND: u64 = 8;
+
+const NANOS: u128 = 1_000_000_000;
+
+/**
+ *  Hands out host frames at a fixed rate
+ *
+ *  Deadlines are counted from a fixed origin instead of from the last wakeup,
+ *  so late wakeups and the rounding of the frame length don't add up over time.
+ * */
+pub struct Timer {
+    // frames per second
+    rate: u64,
+
+    // start of the count and frames handed out since
+    origin: Instant,
+    ticks: u64
+}
 
 impl Timer {
-    pub fn new(interval_ms: u128) -> Timer {
-        Timer(interval_ms)
+    /**
+     *  @func   new         start counting now
+     *
+     *  @param  rate        frames per second
+     * */
+    pub fn new(rate: u64) -> Timer {
+        Timer {
+            rate,
+            origin: Instant::now(),
+            ticks: 0
+        }
+    }
+
+    /**
+     *  @func   next()      time the next frame is due
+     * */
+    pub fn next(&self) -> Instant {
+        self.deadline(self.ticks + 1)
     }
 
-    pub fn run(&self, f: fn()) {
-        
+    /**
+     *  @func   wait()      block until the next frame is due
+     *
+     *  returns the frames that are due, more than one if the caller fell behind
+     * */
+    pub fn wait(&mut self) -> u64 {
+        let next = self.next();
+
+        let now = Instant::now();
+        if next > now + SPIN {
+            thread::sleep(next - now - SPIN);
+        }
+        while Instant::now() < next {
+            thread::yield_now();
+        }
+
         let now = Instant::now();
-        if (now.elapsed().as_millis() % self.0) == 0 {
-            f(); 
-        };
-        self.run(f);
+        let elapsed = now.duration_since(self.origin).as_nanos() * self.rate as u128 / NANOS;
+        let due = elapsed as u64 - self.ticks;
+
+        // too far behind to catch up, start counting again
+        if due > MAX_BEHIND {
+            self.origin = now;
+            self.ticks = 0;
+            return 1;
+        }
+
+        self.ticks += due;
+        due
+    }
+
+    /**
+     *  @func   deadline()  time the given frame is due, rounded up to the next nanosecond
+     * */
+    fn deadline(&self, tick: u64) -> Instant {
+        let rate = self.rate as u128;
+        let nanos = (tick as u128 * NANOS).div_ceil(rate);
+        self.origin + Duration::from_nanos(nanos as u64)
     }
 }
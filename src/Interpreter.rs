@@ -1,3 +1,9 @@
 // interpreter deprecated
 //pub mod interpreter;
 pub mod opcode;
+pub mod machine;
+pub mod platform;
+pub mod rng;
+pub mod cdp1802;
+pub mod timing;
+pub mod analysis;
\ No newline at end of file
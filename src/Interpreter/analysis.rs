@@ -0,0 +1,188 @@
+use std::collections::BTreeMap;
+
+use super::platform::Platform;
+
+/**
+ *  What static analysis finds out about a rom image without running it
+ *
+ *  The code is followed from the entry point through jumps, calls and both ways
+ *  of every skip, so data between the routines isn't counted as instructions.
+ * */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Analysis {
+    // reachable instructions by their pattern, e.g. "DXYN"
+    pub histogram: BTreeMap<&'static str, u64>,
+
+    // platform all reachable instructions run on and where each extension was first seen
+    pub platform: Platform,
+    pub extensions: Vec<(u16, &'static str, Platform)>,
+
+    pub warnings: Vec<String>
+}
+
+/**
+ *  @func   analyze()   follow the code of a rom image from its entry point
+ *
+ *  @param  image       raw rom image
+ *
+ *  @param  base        address the image is loaded to
+ *
+ *  @param  entry       address the program is started at
+ * */
+pub fn analyze(image: &[u8], base: u16, entry: u16) -> Analysis {
+    let mut analysis = Analysis {
+        histogram: BTreeMap::new(),
+        platform: Platform::Vip,
+        extensions: Vec::new(),
+        warnings: Vec::new()
+    };
+
+    let end = base as usize + image.len();
+    let fetch = |addr: usize| -> Option<u16> {
+        if addr < base as usize || addr + 1 >= end {
+            return None;
+        }
+        let offset = addr - base as usize;
+        Some((image[offset] as u16) << 8 | image[offset + 1] as u16)
+    };
+
+    let mut seen = vec![false; end];
+    let mut pending = vec![entry as usize];
+
+    while let Some(addr) = pending.pop() {
+        if addr < end && seen[addr] {
+            continue;
+        }
+        let opc = match fetch(addr) {
+            Some(opc) => opc,
+            None => {
+                warn(&mut analysis.warnings, format!("Code at {:#05x} is outside of the image", addr));
+                continue;
+            },
+        };
+        seen[addr] = true;
+
+        let (name, platform) = pattern(opc);
+        *analysis.histogram.entry(name).or_insert(0) += 1;
+        if platform > Platform::Vip && !analysis.extensions.iter().any(|(_, seen, _)| *seen == name) {
+            analysis.extensions.push((addr as u16, name, platform));
+        }
+        analysis.platform = analysis.platform.max(platform);
+
+        // F000 NNNN is the only instruction that is 4 bytes long
+        let size = |addr: usize| if fetch(addr) == Some(0xF000) { 4 } else { 2 };
+        let next = addr + size(addr);
+        let target = (opc & 0x0FFF) as usize;
+
+        match name {
+            "????" => {
+                warn(&mut analysis.warnings, format!("Unknown opcode {:04X} at {:#05x}", opc, addr));
+            },
+            "00EE" | "00FD" => {},
+            "0NNN" => {
+                warn(&mut analysis.warnings, format!("Machine code at {:#05x} called at {:#05x}", target, addr));
+                pending.push(next);
+            },
+            "1NNN" | "2NNN" => {
+                if !target.is_multiple_of(2) {
+                    warn(&mut analysis.warnings, format!("Jump to odd address {:#05x} at {:#05x}", target, addr));
+                }
+                pending.push(target);
+                if name == "2NNN" {
+                    pending.push(next);
+                }
+            },
+            "BNNN" => {
+                warn(&mut analysis.warnings, format!("Computed jump at {:#05x} isn't followed", addr));
+            },
+            "3XNN" | "4XNN" | "5XY0" | "9XY0" | "EX9E" | "EXA1" => {
+                pending.push(next);
+                pending.push(next + size(next));
+            },
+            _ => pending.push(next),
+        }
+    }
+
+    // data rarely decodes to more than a few instructions before it hits an unknown
+    // opcode, and small values of its bytes look like calls of machine code
+    let instructions: u64 = analysis.histogram.values().sum();
+    let suspicious: u64 = ["????", "0NNN"].iter().filter_map(|name| analysis.histogram.get(name)).sum();
+    if instructions < 2 || 2 * suspicious > instructions {
+        warn(&mut analysis.warnings, format!("Little valid code is reachable from {:#05x}, the image looks like data only", entry));
+    }
+    analysis
+}
+
+/**
+ *  @func   warn()      add a warning once, code reached in several ways would repeat it
+ * */
+fn warn(warnings: &mut Vec<String>, warning: String) {
+    if !warnings.contains(&warning) {
+        warnings.push(warning);
+    }
+}
+
+/**
+ *  @func   pattern()   name of the instruction and the first platform that runs it
+ *
+ *  @param  opc         opcode, F000 is the first half of F000 NNNN
+ * */
+fn pattern(opc: u16) -> (&'static str, Platform) {
+    let x = opc & 0x0F00;
+    match (opc >> 12, opc & 0xFF, opc & 0xF) {
+        _ if opc == 0x0000                  => ("????", Platform::Vip),
+        (0x0, 0xE0, _)                      => ("00E0", Platform::Vip),
+        (0x0, 0xEE, _)                      => ("00EE", Platform::Vip),
+        (0x0, 0xC0..=0xCF, _) if x == 0     => ("00CN", Platform::Schip),
+        (0x0, 0xD0..=0xDF, _) if x == 0     => ("00DN", Platform::XoChip),
+        (0x0, 0xFB, _) if x == 0            => ("00FB", Platform::Schip),
+        (0x0, 0xFC, _) if x == 0            => ("00FC", Platform::Schip),
+        (0x0, 0xFD, _) if x == 0            => ("00FD", Platform::Schip),
+        (0x0, 0xFE, _) if x == 0            => ("00FE", Platform::Schip),
+        (0x0, 0xFF, _) if x == 0            => ("00FF", Platform::Schip),
+        (0x0, _, _)                         => ("0NNN", Platform::Vip),
+        (0x1, _, _)                         => ("1NNN", Platform::Vip),
+        (0x2, _, _)                         => ("2NNN", Platform::Vip),
+        (0x3, _, _)                         => ("3XNN", Platform::Vip),
+        (0x4, _, _)                         => ("4XNN", Platform::Vip),
+        (0x5, _, 0x0)                       => ("5XY0", Platform::Vip),
+        (0x5, _, 0x2)                       => ("5XY2", Platform::XoChip),
+        (0x5, _, 0x3)                       => ("5XY3", Platform::XoChip),
+        (0x6, _, _)                         => ("6XNN", Platform::Vip),
+        (0x7, _, _)                         => ("7XNN", Platform::Vip),
+        (0x8, _, 0x0)                       => ("8XY0", Platform::Vip),
+        (0x8, _, 0x1)                       => ("8XY1", Platform::Vip),
+        (0x8, _, 0x2)                       => ("8XY2", Platform::Vip),
+        (0x8, _, 0x3)                       => ("8XY3", Platform::Vip),
+        (0x8, _, 0x4)                       => ("8XY4", Platform::Vip),
+        (0x8, _, 0x5)                       => ("8XY5", Platform::Vip),
+        (0x8, _, 0x6)                       => ("8XY6", Platform::Vip),
+        (0x8, _, 0x7)                       => ("8XY7", Platform::Vip),
+        (0x8, _, 0xE)                       => ("8XYE", Platform::Vip),
+        (0x9, _, 0x0)                       => ("9XY0", Platform::Vip),
+        (0xA, _, _)                         => ("ANNN", Platform::Vip),
+        (0xB, _, _)                         => ("BNNN", Platform::Vip),
+        (0xC, _, _)                         => ("CXNN", Platform::Vip),
+        (0xD, _, 0x0)                       => ("DXY0", Platform::Schip),
+        (0xD, _, _)                         => ("DXYN", Platform::Vip),
+        (0xE, 0x9E, _)                      => ("EX9E", Platform::Vip),
+        (0xE, 0xA1, _)                      => ("EXA1", Platform::Vip),
+        (0xF, 0x00, _) if x == 0            => ("F000", Platform::XoChip),
+        (0xF, 0x01, _)                      => ("FN01", Platform::XoChip),
+        (0xF, 0x02, _) if x == 0            => ("F002", Platform::XoChip),
+        (0xF, 0x07, _)                      => ("FX07", Platform::Vip),
+        (0xF, 0x0A, _)                      => ("FX0A", Platform::Vip),
+        (0xF, 0x15, _)                      => ("FX15", Platform::Vip),
+        (0xF, 0x18, _)                      => ("FX18", Platform::Vip),
+        (0xF, 0x1E, _)                      => ("FX1E", Platform::Vip),
+        (0xF, 0x29, _)                      => ("FX29", Platform::Vip),
+        (0xF, 0x30, _)                      => ("FX30", Platform::Schip),
+        (0xF, 0x33, _)                      => ("FX33", Platform::Vip),
+        (0xF, 0x3A, _)                      => ("FX3A", Platform::XoChip),
+        (0xF, 0x55, _)                      => ("FX55", Platform::Vip),
+        (0xF, 0x65, _)                      => ("FX65", Platform::Vip),
+        (0xF, 0x75, _)                      => ("FX75", Platform::Schip),
+        (0xF, 0x85, _)                      => ("FX85", Platform::Schip),
+        _                                   => ("????", Platform::Vip),
+    }
+}
@@ -0,0 +1,544 @@
+/*
+ *  ===========================================================
+ *
+ *     Filename:    cdp1802.rs
+ *  Description:    RCA CDP1802 core of the COSMAC VIP, runs
+ *                  0NNN machine code routines or the original
+ *                  interpreter image
+ *
+ *  ===========================================================
+ *
+ *  Register usage of the VIP interpreter (4K machine):
+ *
+ *      R0      display DMA pointer
+ *      R1      interrupt routine (0x8146 in the monitor ROM)
+ *      R2      stack pointer, grows down from 0x0ECF
+ *      R3      program counter of machine code routines
+ *      R4      program counter of the interpreter, a routine returns with D4 (SEP R4)
+ *      R5      CHIP-8 program counter
+ *      R6, R7  pointer to VX, VY
+ *      R8      timers, R8.1 delay and R8.0 sound
+ *      R9      random number seed
+ *      RA      I
+ *      RB      RB.1 display page
+ *
+ *  The variables V0-VF live at 0x0EF0 and the display at 0x0F00.
+ * */
+
+use super::super::Drivers::display::{FBuffer, HORIZONTAL};
+use super::super::Drivers::keyboard_io::Keypad;
+use super::super::Drivers::memory::{Memory, Registers, Region, FaultKind, MODE, FONTSET, MEMSIZE};
+use super::machine::fnv1a;
+use super::rng::Rng;
+use super::super::error::Chip8Error;
+
+// machine cycles (8 clocks at 1.7609MHz) per 60Hz frame
+pub const CYCLES_PER_FRAME: u64 = 3668;
+
+// a routine that doesn't return within a second is considered hung
+const CALL_LIMIT: u64 = 60 * CYCLES_PER_FRAME;
+
+const VARIABLES: usize = 0x0EF0;
+const STACK_POINTER: u16 = 0x0ECF;
+const MONITOR: u16 = 0x8000;
+
+/**
+ *  CDP1802 registers and the VIP peripherals attached to its I/O lines
+ *
+ *  - OUT 1 / INP 1     turn the CDP1861 display off / on
+ *  - OUT 2             select the key EF3 reports on
+ *  - Q                 tone output
+ * */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cdp1802 {
+    pub r: [u16; 16],
+    pub d: u8,
+    pub df: bool,
+    pub p: u8,
+    pub x: u8,
+    pub t: u8,
+    pub ie: bool,
+    pub q: bool,
+
+    // machine cycles executed since power on
+    pub cycles: u64,
+
+    // IDL, waiting for the next interrupt
+    idle: bool,
+
+    key_latch: u8,
+    display_on: bool
+}
+
+impl Cdp1802 {
+    /**
+     *  @func   new()       CPU state after a reset
+     * */
+    pub fn new() -> Cdp1802 {
+        Cdp1802 {
+            r: [0; 16],
+            d: 0,
+            df: false,
+            p: 0,
+            x: 0,
+            t: 0,
+            ie: true,
+            q: false,
+
+            cycles: 0,
+
+            idle: false,
+
+            key_latch: 0,
+            display_on: false
+        }
+    }
+
+    /**
+     *  @func   boot()      CPU state the monitor hands over to the interpreter at 0x0000
+     *
+     *  the monitor leaves the page at the top of RAM in R1.1, the interpreter
+     *  uses it as display page
+     * */
+    pub fn boot() -> Cdp1802 {
+        let mut cpu = Cdp1802::new();
+        cpu.r[1] = ((MEMSIZE - 0x100) & 0xFF00) as u16;
+        cpu
+    }
+
+    /**
+     *  @func   state_hash()    feed the CPU state into a running FNV-1a hash
+     *
+     *  @param  hash            hash of the rest of the machine
+     * */
+    pub fn state_hash(&self, mut hash: u64) -> u64 {
+        for reg in self.r.iter() {
+            hash = fnv1a(Some(hash), &reg.to_be_bytes());
+        }
+        hash = fnv1a(Some(hash), &self.cycles.to_be_bytes());
+        fnv1a(Some(hash), &[self.d, self.df as u8, self.p, self.x, self.t, self.ie as u8, self.q as u8,
+            self.idle as u8, self.key_latch, self.display_on as u8])
+    }
+
+    /**
+     *  @func   call()      run a 0NNN machine code routine until it returns with SEP R4
+     *
+     *  @param  opc         0NNN instruction
+     *
+     *  The interpreter state is mirrored into memory the way the VIP keeps it
+     *  (see the register usage above) and copied back once the routine returns.
+     *  No interrupts are raised during a call, IDL returns immediately.
+     * */
+    pub fn call(&mut self, opc: u16, pmem: &mut Memory, preg: &mut Registers, fbuffer: &mut FBuffer,
+                keypad: &Keypad, rng: &mut Rng) -> Result<(), Chip8Error> {
+        let addr = opc & 0xFFF;
+        let display = Region::Display.range().start;
+
+        for (n, value) in preg.register_array.iter().enumerate() {
+            pmem.rw_memory(VARIABLES + n, MODE::WRITE(*value))?;
+        }
+        for (n, line) in fbuffer.iter().enumerate() {
+            for (byte, pixels) in line.chunks(8).enumerate() {
+                let value = pixels.iter().fold(0, |value, pixel| (value << 1) | (*pixel & 1));
+                pmem.rw_memory(display + n * HORIZONTAL / 8 + byte, MODE::WRITE(value))?;
+            }
+        }
+
+        self.r[2] = STACK_POINTER;
+        self.r[3] = addr;
+        self.r[5] = preg.eip.wrapping_add(2);
+        self.r[6] = (VARIABLES + ((opc & 0x0F00) >> 8) as usize) as u16;
+        self.r[7] = (VARIABLES + ((opc & 0x00F0) >> 4) as usize) as u16;
+        self.r[8] = ((preg.delay_timer as u16) << 8) | preg.sound_timer as u16;
+        if let Rng::Vip(r9) = rng {
+            self.r[9] = *r9;
+        }
+        self.r[0xA] = preg.address_register;
+        self.r[0xB] = display as u16;
+        self.p = 3;
+        self.x = 2;
+
+        let mut cycles = 0;
+        while self.p != 4 {
+            if cycles >= CALL_LIMIT {
+                return Err(Chip8Error::MachineCodeTimeout { pc: preg.eip, addr });
+            }
+            cycles += self.step(pmem, keypad)?;
+            self.idle = false;
+        }
+
+        for n in 0..preg.register_array.len() {
+            preg.register_array[n] = pmem.rw_memory(VARIABLES + n, MODE::READ)?;
+        }
+        self.refresh(pmem, fbuffer, display)?;
+
+        preg.address_register = self.r[0xA];
+        preg.delay_timer = (self.r[8] >> 8) as u8;
+        preg.sound_timer = self.r[8] as u8;
+        if let Rng::Vip(r9) = rng {
+            *r9 = self.r[9];
+        }
+
+        // the routine may move the CHIP-8 program counter, step() advances past the instruction
+        preg.eip = self.r[5].wrapping_sub(2);
+        Ok(())
+    }
+
+    /**
+     *  @func   run_frame()     run the booted interpreter image for one 60Hz frame
+     *
+     *  @param  fbuffer         refreshed from the display page while the display is on
+     *
+     *  The interrupt routine of the monitor ROM is not available, if R1 points
+     *  into the ROM its effect is applied directly: the display page RB.1 is
+     *  shown and the timers in R8 count down.
+     * */
+    pub fn run_frame(&mut self, pmem: &mut Memory, fbuffer: &mut FBuffer, keypad: &Keypad) -> Result<(), Chip8Error> {
+        if self.display_on {
+            self.idle = false;
+            self.refresh(pmem, fbuffer, (self.r[0xB] & 0xFF00) as usize)?;
+
+            if self.ie && self.r[1] >= MONITOR {
+                let delay = (self.r[8] >> 8) as u8;
+                let sound = self.r[8] as u8;
+                self.r[8] = ((delay.saturating_sub(1) as u16) << 8) | sound.saturating_sub(1) as u16;
+                self.q = sound > 1;
+            } else if self.ie {
+                self.t = (self.x << 4) | self.p;
+                self.x = 2;
+                self.p = 1;
+                self.ie = false;
+            }
+        }
+
+        let mut cycles = 0;
+        while cycles < CYCLES_PER_FRAME && !self.idle {
+            let pc = self.r[self.p as usize];
+            cycles += self.step(pmem, keypad).map_err(|err| err.with_pc(pc))?;
+        }
+        Ok(())
+    }
+
+    /**
+     *  @func   refresh()   copy a display page into the framebuffer (what the CDP1861 DMA shows)
+     *
+     *  @param  page        address of the 256 byte display page
+     * */
+    fn refresh(&self, pmem: &mut Memory, fbuffer: &mut FBuffer, page: usize) -> Result<(), Chip8Error> {
+        for (y, line) in fbuffer.iter_mut().enumerate() {
+            for (x, pixel) in line.iter_mut().enumerate() {
+                let value = pmem.rw_memory(page + (y * HORIZONTAL + x) / 8, MODE::READ)?;
+                *pixel = (value >> (7 - x % 8)) & 1;
+            }
+        }
+        Ok(())
+    }
+
+    /**
+     *  @func   step()      execute a single instruction
+     *
+     *  returns the number of machine cycles the instruction took
+     * */
+    pub fn step(&mut self, pmem: &mut Memory, keypad: &Keypad) -> Result<u64, Chip8Error> {
+        let p = self.p as usize;
+        let x = self.x as usize;
+
+        // routines of the monitor ROM are not emulated
+        if self.r[p] >= MONITOR {
+            return Err(Chip8Error::MemoryFault { pc: None, addr: self.r[p] as usize, kind: FaultKind::OutOfBounds });
+        }
+        let opc = self.read(pmem, self.r[p])?;
+        self.r[p] = self.r[p].wrapping_add(1);
+
+        let n = (opc & 0xF) as usize;
+
+        match opc >> 4 {
+            0x0 if n == 0 => self.idle = true,                                  // IDL
+            0x0 => self.d = self.read(pmem, self.r[n])?,                        // LDN
+            0x1 => self.r[n] = self.r[n].wrapping_add(1),                       // INC
+            0x2 => self.r[n] = self.r[n].wrapping_sub(1),                       // DEC
+            0x3 => {
+                // BR, BQ, BZ, BDF, B1-B4 and their negations, 38 (SKP) never branches
+                let cond = match n & 0x7 {
+                    0x0 => true,
+                    0x1 => self.q,
+                    0x2 => self.d == 0,
+                    0x3 => self.df,
+                    flag => self.flag(flag as u8 - 3, keypad),
+                };
+                if cond != (n >= 0x8) {
+                    let target = self.read(pmem, self.r[p])?;
+                    self.r[p] = (self.r[p] & 0xFF00) | target as u16;
+                } else {
+                    self.r[p] = self.r[p].wrapping_add(1);
+                }
+            },
+            0x4 => {                                                            // LDA
+                self.d = self.read(pmem, self.r[n])?;
+                self.r[n] = self.r[n].wrapping_add(1);
+            },
+            0x5 => self.write(pmem, self.r[n], self.d)?,                        // STR
+            0x6 if n == 0 => self.r[x] = self.r[x].wrapping_add(1),             // IRX
+            0x6 if n < 0x8 => {                                                 // OUT
+                let value = self.read(pmem, self.r[x])?;
+                self.r[x] = self.r[x].wrapping_add(1);
+                self.output(n, value);
+            },
+            0x6 => {                                                            // INP
+                let value = self.input(n - 0x8);
+                self.write(pmem, self.r[x], value)?;
+                self.d = value;
+            },
+            0x7 => match n {
+                0x0 | 0x1 => {                                                  // RET, DIS
+                    let value = self.read(pmem, self.r[x])?;
+                    self.r[x] = self.r[x].wrapping_add(1);
+                    self.x = value >> 4;
+                    self.p = value & 0xF;
+                    self.ie = n == 0x0;
+                },
+                0x2 => {                                                        // LDXA
+                    self.d = self.read(pmem, self.r[x])?;
+                    self.r[x] = self.r[x].wrapping_add(1);
+                },
+                0x3 => {                                                        // STXD
+                    self.write(pmem, self.r[x], self.d)?;
+                    self.r[x] = self.r[x].wrapping_sub(1);
+                },
+                0x6 => self.shift(false, true),                                 // SHRC
+                0x8 => self.write(pmem, self.r[x], self.t)?,                    // SAV
+                0x9 => {                                                        // MARK
+                    self.t = (self.x << 4) | self.p;
+                    self.write(pmem, self.r[2], self.t)?;
+                    self.x = self.p;
+                    self.r[2] = self.r[2].wrapping_sub(1);
+                },
+                0xA => self.q = false,                                          // REQ
+                0xB => self.q = true,                                           // SEQ
+                0xE => self.shift(true, true),                                  // SHLC
+                _ => {
+                    // ADC, SDB, SMB and their immediate forms
+                    let value = self.operand(pmem, n >= 0x8)?;
+                    match n & 0x7 {
+                        0x4 => self.add(value, self.d, self.df),
+                        0x5 => self.sub(value, self.d, !self.df),
+                        _   => self.sub(self.d, value, !self.df),
+                    }
+                },
+            },
+            0x8 => self.d = self.r[n] as u8,                                    // GLO
+            0x9 => self.d = (self.r[n] >> 8) as u8,                             // GHI
+            0xA => self.r[n] = (self.r[n] & 0xFF00) | self.d as u16,            // PLO
+            0xB => self.r[n] = (self.r[n] & 0x00FF) | ((self.d as u16) << 8),   // PHI
+            0xC => {
+                // long branches and skips, C4 is NOP
+                let (branch, cond) = match n {
+                    0x0 => (true, true),
+                    0x1 => (true, self.q),
+                    0x2 => (true, self.d == 0),
+                    0x3 => (true, self.df),
+                    0x4 => (false, false),
+                    0x5 => (false, !self.q),
+                    0x6 => (false, self.d != 0),
+                    0x7 => (false, !self.df),
+                    0x8 => (false, true),
+                    0x9 => (true, !self.q),
+                    0xA => (true, self.d != 0),
+                    0xB => (true, !self.df),
+                    0xC => (false, self.ie),
+                    0xD => (false, self.q),
+                    0xE => (false, self.d == 0),
+                    _   => (false, self.df),
+                };
+                if branch && cond {
+                    let high = self.read(pmem, self.r[p])?;
+                    let low = self.read(pmem, self.r[p].wrapping_add(1))?;
+                    self.r[p] = ((high as u16) << 8) | low as u16;
+                } else if branch || cond {
+                    self.r[p] = self.r[p].wrapping_add(2);
+                }
+                self.cycles += 3;
+                return Ok(3);
+            },
+            0xD => self.p = n as u8,                                            // SEP
+            0xE => self.x = n as u8,                                            // SEX
+            _ => match n {
+                0x6 => self.shift(false, false),                                // SHR
+                0xE => self.shift(true, false),                                 // SHL
+                _ => {
+                    // LDX, OR, AND, XOR, ADD, SD, SM and their immediate forms
+                    let value = self.operand(pmem, n >= 0x8)?;
+                    match n & 0x7 {
+                        0x0 => self.d = value,
+                        0x1 => self.d |= value,
+                        0x2 => self.d &= value,
+                        0x3 => self.d ^= value,
+                        0x4 => self.add(value, self.d, false),
+                        0x5 => self.sub(value, self.d, false),
+                        _   => self.sub(self.d, value, false),
+                    }
+                },
+            },
+        }
+
+        self.cycles += 2;
+        Ok(2)
+    }
+
+    /**
+     *  @func   operand()   M(R(X)), or the immediate byte at M(R(P))
+     * */
+    fn operand(&mut self, pmem: &mut Memory, immediate: bool) -> Result<u8, Chip8Error> {
+        if immediate {
+            let p = self.p as usize;
+            let value = self.read(pmem, self.r[p])?;
+            self.r[p] = self.r[p].wrapping_add(1);
+            Ok(value)
+        } else {
+            self.read(pmem, self.r[self.x as usize])
+        }
+    }
+
+    fn add(&mut self, a: u8, b: u8, carry: bool) {
+        let sum = a as u16 + b as u16 + carry as u16;
+        self.d = sum as u8;
+        self.df = sum > 0xFF;
+    }
+
+    // DF is set if no borrow occurred
+    fn sub(&mut self, a: u8, b: u8, borrow: bool) {
+        let diff = a as i16 - b as i16 - borrow as i16;
+        self.d = diff as u8;
+        self.df = diff >= 0;
+    }
+
+    fn shift(&mut self, left: bool, with_carry: bool) {
+        let carry = (with_carry && self.df) as u8;
+
+        if left {
+            self.df = self.d & 0x80 != 0;
+            self.d = (self.d << 1) | carry;
+        } else {
+            self.df = self.d & 0x01 != 0;
+            self.d = (self.d >> 1) | (carry << 7);
+        }
+    }
+
+    /**
+     *  @func   flag()      state of the external flag EF1-EF4
+     *
+     *  only EF3 is connected, it reports the key selected by OUT 2
+     * */
+    fn flag(&self, ef: u8, keypad: &Keypad) -> bool {
+        ef == 3 && keypad.is_pressed(self.key_latch)
+    }
+
+    fn output(&mut self, port: usize, value: u8) {
+        match port {
+            1 => self.display_on = false,
+            2 => self.key_latch = value & 0xF,
+            _ => {},
+        }
+    }
+
+    fn input(&mut self, port: usize) -> u8 {
+        if port == 1 {
+            self.display_on = true;
+        }
+        0x00
+    }
+
+    /**
+     *  @func   read()      read RAM or the monitor ROM at 0x8000
+     *
+     *  of the ROM only the table FX29 reads is available: the low byte of
+     *  each digit's sprite at 0x8100 and the sprites right after it
+     * */
+    fn read(&self, pmem: &mut Memory, addr: u16) -> Result<u8, Chip8Error> {
+        if addr < MONITOR {
+            return pmem.rw_memory(addr as usize, MODE::READ);
+        }
+
+        Ok(match addr & 0x1FF {
+            offset @ 0x100..=0x10F => 0x10 + (offset & 0xF) as u8 * 5,
+            offset @ 0x110..=0x15F => FONTSET[offset as usize - 0x110],
+            _ => 0x00,
+        })
+    }
+
+    // the ROM ignores writes
+    fn write(&self, pmem: &mut Memory, addr: u16, value: u8) -> Result<(), Chip8Error> {
+        if addr < MONITOR {
+            pmem.rw_memory(addr as usize, MODE::WRITE(value))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // machine code at 0x300 run with P = 3, as a 0NNN routine would be
+    fn run(code: &[u8], steps: usize, setup: fn(&mut Cdp1802)) -> (Cdp1802, Memory) {
+        let mut pmem = Memory::new();
+        pmem.load(code.to_vec(), 0x300).unwrap();
+
+        let mut cpu = Cdp1802::new();
+        cpu.p = 3;
+        cpu.r[3] = 0x300;
+        setup(&mut cpu);
+
+        for _ in 0..steps {
+            cpu.step(&mut pmem, &Keypad::new()).unwrap();
+        }
+        (cpu, pmem)
+    }
+
+    #[test]
+    fn sdb_subtracts_d_and_the_pending_borrow() {
+        // SDBI 30: D = 30 - 10 - 1
+        let (cpu, _) = run(&[0x7D, 0x30], 1, |cpu| { cpu.d = 0x10; cpu.df = false; });
+        assert_eq!((cpu.d, cpu.df), (0x1F, true));
+
+        // SDBI 30: D = 30 - 40, DF clear signals the borrow
+        let (cpu, _) = run(&[0x7D, 0x30], 1, |cpu| { cpu.d = 0x40; cpu.df = true; });
+        assert_eq!((cpu.d, cpu.df), (0xF0, false));
+    }
+
+    #[test]
+    fn smb_subtracts_memory_and_the_pending_borrow() {
+        // SMB with R(X) at the 05 behind the instruction: D = 10 - 05 - 1
+        let (cpu, _) = run(&[0x77, 0x05], 1, |cpu| { cpu.x = 4; cpu.r[4] = 0x301; cpu.d = 0x10; cpu.df = false; });
+        assert_eq!((cpu.d, cpu.df), (0x0A, true));
+
+        // SMBI 00: D = 00 - 00 - 1
+        let (cpu, _) = run(&[0x7F, 0x00], 1, |cpu| { cpu.d = 0x00; cpu.df = false; });
+        assert_eq!((cpu.d, cpu.df), (0xFF, false));
+    }
+
+    #[test]
+    fn lskp_skips_two_bytes_in_three_cycles() {
+        let (cpu, _) = run(&[0xC8, 0x12, 0x34], 1, |_| {});
+        assert_eq!((cpu.r[3], cpu.cycles), (0x303, 3));
+    }
+
+    #[test]
+    fn lbnz_branches_on_nonzero_d_only() {
+        let (cpu, _) = run(&[0xCA, 0x04, 0x56], 1, |cpu| cpu.d = 0x01);
+        assert_eq!(cpu.r[3], 0x456);
+
+        let (cpu, _) = run(&[0xCA, 0x04, 0x56], 1, |cpu| cpu.d = 0x00);
+        assert_eq!(cpu.r[3], 0x303);
+    }
+
+    #[test]
+    fn mark_and_ret_restore_x_and_p() {
+        // MARK, SEX 2, INC R2, RET
+        let (cpu, mut pmem) = run(&[0x79, 0xE2, 0x12, 0x70], 4, |cpu| { cpu.x = 5; cpu.r[2] = 0x3F0; cpu.ie = false; });
+
+        assert_eq!(cpu.t, 0x53);
+        assert_eq!(pmem.rw_memory(0x3F0, MODE::READ).unwrap(), 0x53);
+        assert_eq!((cpu.x, cpu.p, cpu.r[2], cpu.ie), (5, 3, 0x3F1, true));
+    }
+}
@@ -0,0 +1,365 @@
+use super::super::Drivers::display::{FBuffer, HORIZONTAL, VERTICAL};
+use super::super::Drivers::keyboard_io::Keypad;
+use super::super::Drivers::memory::{Memory, Registers, MODE, FONTSET};
+use super::cdp1802::Cdp1802;
+use super::opcode::{Operations, TrapHandler};
+use super::rng::Rng;
+use super::timing::{vip_cycles, Timing, VIP_CYCLES_PER_FRAME};
+use super::super::error::Chip8Error;
+
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/**
+ *  @func   fnv1a()     feed bytes into a 64 bit FNV-1a hash
+ *
+ *  @param  hash        running hash, None starts a new one
+ *
+ *  @param  bytes       data to be hashed
+ * */
+pub fn fnv1a(hash: Option<u64>, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash.unwrap_or(FNV_OFFSET), |hash, byte| (hash ^ *byte as u64).wrapping_mul(FNV_PRIME))
+}
+
+/**
+ *  Complete state of the emulated CHIP-8
+ *
+ *  Everything that influences execution lives in here, so running the same
+ *  ROM with the same seed and the same keypad input per frame always ends
+ *  in the same state (see state_hash()).
+ * */
+pub struct Machine {
+    pub mem: Memory,
+    pub reg: Registers,
+    pub opcodes: Operations,
+    pub keypad: Keypad,
+    pub framebuffer: FBuffer,
+    pub rng: Rng,
+
+    // runs 0NNN with Policy::Native, or everything once an interpreter image is booted
+    pub cpu: Cdp1802,
+    pub booted: bool,
+
+    // VIP timing: machine cycles left in this frame, and if DXYN may draw without waiting
+    pub timing: Timing,
+    cycles: i64,
+    vblank: bool,
+
+    // called for 0NNN and unknown opcodes with Policy::Trap
+    pub trap: Option<TrapHandler>,
+
+    // number of frames (60Hz ticks) executed since power on
+    pub frame: u64
+}
+
+impl Machine {
+    /**
+     *  @func   new()       Create a powered on machine with the font loaded
+     *
+     *  @param  opcodes     opcode handler (carries the quirk settings)
+     *
+     *  @param  entry       address execution starts at
+     *
+     *  @param  rng         random number source used by CXNN
+     * */
+    pub fn new(opcodes: Operations, entry: u16, rng: Rng) -> Result<Machine, Chip8Error> {
+        let mut mem = Memory::new();
+        mem.load(FONTSET.to_vec(), opcodes.sprite_address() as usize)?;
+
+        Ok(Machine {
+            mem,
+            reg: Registers::new(entry),
+            opcodes,
+            keypad: Keypad::new(),
+            framebuffer: [[0; HORIZONTAL]; VERTICAL],
+            rng,
+
+            cpu: Cdp1802::new(),
+            booted: false,
+
+            timing: Timing::Ipf,
+            cycles: 0,
+            vblank: false,
+
+            trap: None,
+
+            frame: 0
+        })
+    }
+
+    /**
+     *  @func   boot()      Load an interpreter image to 0x0000 and run it on the CDP1802
+     *
+     *  @param  image       interpreter image, e.g. the 512 byte VIP CHIP-8 interpreter
+     *
+     *  the image replaces the font and keeps its own state in memory, the
+     *  registers of this machine are no longer used
+     * */
+    pub fn boot(&mut self, image: Vec<u8>) -> Result<(), Chip8Error> {
+        self.mem.load(image, 0x0000)?;
+        self.cpu = Cdp1802::boot();
+        self.booted = true;
+        Ok(())
+    }
+
+    /**
+     *  @func   run_frame()     execute one 60Hz frame
+     *
+     *  @param  ipf             instructions executed per frame (only used with Timing::Ipf)
+     * */
+    pub fn run_frame(&mut self, ipf: u32) -> Result<(), Chip8Error> {
+        if self.booted {
+            self.cpu.run_frame(&mut self.mem, &mut self.framebuffer, &self.keypad)?;
+        } else if self.timing == Timing::Vip {
+            // the video interrupt ticks the timers and releases a waiting DXYN
+            self.reg.delay_timer = self.reg.delay_timer.saturating_sub(1);
+            self.reg.sound_timer = self.reg.sound_timer.saturating_sub(1);
+            self.vblank = true;
+
+            // an instruction overrunning the frame is paid for by the next one
+            self.cycles += VIP_CYCLES_PER_FRAME as i64;
+            while self.cycles > 0 {
+                let eip = self.reg.eip;
+                if !self.vblank && self.fetch().map_err(|err| err.with_pc(eip))? & 0xF000 == 0xD000 {
+                    self.cycles = 0;
+                    break;
+                }
+                self.cycles -= self.step()? as i64;
+            }
+        } else {
+            for _ in 0..ipf {
+                self.step()?;
+            }
+
+            self.reg.delay_timer = self.reg.delay_timer.saturating_sub(1);
+            self.reg.sound_timer = self.reg.sound_timer.saturating_sub(1);
+        }
+        self.frame += 1;
+
+        Ok(())
+    }
+
+    /**
+     *  @func   sound()     the buzzer is on, the sound timer runs or a booted interpreter set Q
+     * */
+    pub fn sound(&self) -> bool {
+        if self.booted {
+            self.cpu.q
+        } else {
+            self.reg.sound_timer > 0
+        }
+    }
+
+    /**
+     *  @func   step()      fetch, execute and advance past a single instruction
+     *
+     *  returns the machine cycles the instruction takes on the VIP
+     * */
+    pub fn step(&mut self) -> Result<u64, Chip8Error> {
+        let eip = self.reg.eip;
+        let native = self.cpu.cycles;
+
+        // tag faults with the instruction that caused them
+        let opc = self.fetch().map_err(|err| err.with_pc(eip))?;
+        let vx = self.reg.register_array[((opc & 0x0F00) >> 8) as usize];
+        self.execute(opc).map_err(|err| err.with_pc(eip))?;
+
+        let skipped = matches!(opc & 0xF000, 0x3000 | 0x4000 | 0x5000 | 0x9000 | 0xE000) && self.reg.eip != eip;
+        // only the instruction right after the interrupt catches it, a later DXYN waits for the next one
+        self.vblank = false;
+        self.reg.eip = self.reg.eip.wrapping_add(0x2);
+
+        Ok(vip_cycles(opc, vx, skipped) + self.cpu.cycles - native)
+    }
+
+    /**
+     *  @func   fetch()     read the instruction at eip
+     * */
+    fn fetch(&mut self) -> Result<u16, Chip8Error> {
+        let eip = self.reg.eip as usize;
+        Ok(((self.mem.rw_memory(eip, MODE::READ)? as u16) << 8) | self.mem.rw_memory(eip + 1, MODE::READ)? as u16)
+    }
+
+    /**
+     *  @func   state_hash()    FNV-1a hash over the complete machine state
+     *
+     *  used by movies to detect desyncs during playback
+     * */
+    pub fn state_hash(&self) -> u64 {
+        let mut hash = fnv1a(None, &self.mem.mem);
+
+        for ret in self.mem.stack() {
+            hash = fnv1a(Some(hash), &ret.to_be_bytes());
+        }
+        hash = fnv1a(Some(hash), &self.reg.register_array);
+        hash = fnv1a(Some(hash), &self.reg.address_register.to_be_bytes());
+        hash = fnv1a(Some(hash), &self.reg.eip.to_be_bytes());
+        hash = fnv1a(Some(hash), &[self.reg.delay_timer, self.reg.sound_timer]);
+        hash = fnv1a(Some(hash), &self.keypad.state.to_be_bytes());
+        hash = fnv1a(Some(hash), &self.rng.state().to_be_bytes());
+        hash = self.cpu.state_hash(hash);
+        hash = fnv1a(Some(hash), &self.cycles.to_be_bytes());
+        hash = fnv1a(Some(hash), &[self.vblank as u8]);
+        for line in self.framebuffer.iter() {
+            hash = fnv1a(Some(hash), line);
+        }
+
+        fnv1a(Some(hash), &self.frame.to_be_bytes())
+    }
+
+    /**
+    *  @func   execute()   execute instruction
+    *
+    *  @param  opc         opcode of instruction
+    * */
+    fn execute(&mut self, opc: u16) -> Result<(), Chip8Error> {
+        let MSN: u8 = ((0xF000 & opc) >> 12) as u8;
+
+        let opcodes = &mut self.opcodes;
+        let pmem = &mut self.mem;
+        let preg = &mut self.reg;
+        let trap = &mut self.trap;
+
+        match MSN {
+            0x0 => {
+                match opc {
+                    0x00E0 => opcodes.clear_display(&mut self.framebuffer),
+                    0x00EE => opcodes.return_from_call(pmem, preg),
+                    _ if opcodes.native_machine_code() => {
+                        self.cpu.call(opc, pmem, preg, &mut self.framebuffer, &self.keypad, &mut self.rng)
+                    },
+                    _ => opcodes.call_machine_code(opc, pmem, preg, trap),
+                }
+            },
+            0x1 => opcodes.jmp_address(opc, preg),
+            0x2 => opcodes.call_subroutine(opc, pmem, preg),
+            0x3 => opcodes.reg_val_compare(opc, preg),
+            0x4 => opcodes.reg_val_noncompare(opc, preg),
+            0x5 => opcodes.reg_compare(opc, preg),
+            0x6 => opcodes.reg_set(opc, preg),
+            0x7 => opcodes.reg_add(opc, preg),
+            0x8 => {
+                // least significant nibble
+                let LSN: u8  = (opc & 0xF) as u8;
+
+                match LSN {
+                    0x0 => opcodes.reg_assign(opc, preg),
+                    0x1 => opcodes.reg_or(opc, preg),
+                    0x2 => opcodes.reg_and(opc, preg),
+                    0x3 => opcodes.reg_xor(opc, preg),
+                    0x4 => opcodes.reg_add_reg(opc, preg),
+                    0x5 => opcodes.regx_sub_regy(opc, preg),
+                    0x6 => opcodes.lsb_shift_right(opc, preg),
+                    0x7 => opcodes.regy_sub_regx(opc, preg),
+                    0xE => opcodes.lsb_shift_left(opc, preg),
+                    _ => opcodes.unknown_opcode(opc, pmem, preg, trap),
+                }
+            },
+            0x9 => opcodes.reg_noncompare(opc, preg),
+            0xA => opcodes.jmp_I(opc, preg),
+            0xB => opcodes.jmp_offset(opc, preg),
+            0xC => opcodes.rand_reg(opc, pmem, preg, &mut self.rng),
+            0xD => opcodes.draw_sprite(opc, pmem, preg, &mut self.framebuffer),
+            0xE => {
+                let DLSN: u8 = (opc & 0xFF) as u8;
+                match DLSN {
+                    0x9E => opcodes.stored_key_pressed(opc, preg, &self.keypad),
+                    0xA1 => opcodes.stored_key_notpressed(opc, preg, &self.keypad),
+                    _ => opcodes.unknown_opcode(opc, pmem, preg, trap),
+                }
+            },
+            0xF => {
+                let DLSN: u8 = (opc & 0xFF) as u8;
+
+                match DLSN {
+                    0x07 => opcodes.get_delay(opc, preg),
+                    0x0A => opcodes.await_press(opc, preg, &self.keypad),
+                    0x15 => opcodes.set_delay_timer(opc, preg),
+                    0x18 => opcodes.set_sound_timer(opc, preg),
+                    0x1E => opcodes.reg_add_I(opc, preg),
+                    0x29 => opcodes.set_I_sprite_reg(opc, preg),
+                    0x33 => opcodes.store_bcd_at_I(opc, pmem, preg),
+                    0x55 => opcodes.write_reg_mem(opc, pmem, preg),
+                    0x65 => opcodes.read_reg_mem(opc, pmem, preg),
+                    _ => opcodes.unknown_opcode(opc, pmem, preg, trap),
+                }
+            },
+            _   => opcodes.unknown_opcode(opc, pmem, preg, trap),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::opcode::Policy;
+    use super::super::super::Drivers::memory::FaultKind;
+
+    fn machine(image: &[u8]) -> Machine {
+        let opcodes = Operations::new(true, 0x050, Policy::Ignore, Policy::Halt);
+        let mut machine = Machine::new(opcodes, 0x200, Rng::new("xorshift", 1).unwrap()).unwrap();
+        machine.mem.load(image.to_vec(), 0x200).unwrap();
+        machine
+    }
+
+    #[test]
+    fn stack_overflow_carries_the_pc() {
+        // 0x200: call 0x202, 0x202: call 0x202
+        let mut machine = machine(&[0x22, 0x02, 0x22, 0x02]);
+        machine.mem.stack_depth = 2;
+
+        machine.step().unwrap();
+        machine.step().unwrap();
+        match machine.step() {
+            Err(Chip8Error::StackOverflow { pc, stack }) => {
+                assert_eq!(pc, Some(0x202));
+                assert_eq!(stack, [0x200, 0x202]);
+            },
+            other => panic!("expected a stack overflow, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn stack_underflow_carries_the_pc() {
+        // 0x200: clear, 0x202: return with nothing on the stack
+        let mut machine = machine(&[0x00, 0xE0, 0x00, 0xEE]);
+
+        machine.step().unwrap();
+        match machine.step() {
+            Err(Chip8Error::StackUnderflow { pc }) => assert_eq!(pc, Some(0x202)),
+            other => panic!("expected a stack underflow, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn register_dump_past_the_memory_faults() {
+        // 0x200: I := 0xFFF, 0x202: save v0 - v1 to 0xFFF and 0x1000
+        let mut machine = machine(&[0xAF, 0xFF, 0xF1, 0x55]);
+
+        machine.step().unwrap();
+        match machine.step() {
+            Err(Chip8Error::MemoryFault { pc, addr, kind }) => {
+                assert_eq!(pc, Some(0x202));
+                assert_eq!(addr, 0x1000);
+                assert_eq!(kind, FaultKind::OutOfBounds);
+            },
+            other => panic!("expected a memory fault, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn fetch_past_the_memory_faults() {
+        // 0x200: jump 0xFFF, the second byte of the instruction there is outside of the memory
+        let mut machine = machine(&[0x1F, 0xFF]);
+
+        machine.step().unwrap();
+        match machine.step() {
+            Err(Chip8Error::MemoryFault { pc, addr, kind }) => {
+                assert_eq!(pc, Some(0xFFF));
+                assert_eq!(addr, 0x1000);
+                assert_eq!(kind, FaultKind::OutOfBounds);
+            },
+            other => panic!("expected a memory fault, got {:?}", other),
+        }
+    }
+}
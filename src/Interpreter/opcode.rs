@@ -1,200 +1,297 @@
-#[path="../Drivers/display.rs"]
-mod display;
-
+use super::super::Drivers::display::{FBuffer, HORIZONTAL, VERTICAL};
+use super::super::Drivers::keyboard_io::Keypad;
 use super::super::Drivers::memory::{Memory, Registers, MODE};
+use super::rng::Rng;
+use super::super::error::Chip8Error;
+use std::collections::HashSet;
+use std::io;
+
+/**
+ *  What to do with a 0NNN machine code call or an opcode that can't be decoded
+ *  - Halt      stop the machine with an error
+ *  - Ignore    log it and treat the instruction as a NOP
+ *  - Trap      hand it to the host's trap handler
+ *  - Native    run the routine on the CDP1802 core (0NNN only)
+ * */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Policy {
+    Halt,
+    Ignore,
+    Trap,
+    Native
+}
 
-// TODO: Rust lifetime 'a for references to memory and registers
+impl Policy {
+    /**
+     *  @func   from_name()     policy by its commandline name
+     *
+     *  @param  name            "halt", "ignore", "trap" or "native"
+     * */
+    pub fn from_name(name: &str) -> Result<Policy, io::Error> {
+        match name {
+            "halt"      => Ok(Policy::Halt),
+            "ignore"    => Ok(Policy::Ignore),
+            "trap"      => Ok(Policy::Trap),
+            "native"    => Ok(Policy::Native),
+            _           => Err(io::Error::new(io::ErrorKind::InvalidInput, "Unknown policy")),
+        }
+    }
+
+    /**
+     *  @func   name()          commandline name of the policy
+     * */
+    pub fn name(self) -> &'static str {
+        match self {
+            Policy::Halt    => "halt",
+            Policy::Ignore  => "ignore",
+            Policy::Trap    => "trap",
+            Policy::Native  => "native",
+        }
+    }
+}
+
+/**
+ *  Host callback for Policy::Trap, called with the opcode and the machine's
+ *  memory and registers (eip points at the trapping instruction)
+ * */
+pub type TrapHandler = Box<dyn FnMut(u16, &mut Memory, &mut Registers) -> Result<(), Chip8Error>>;
 
 pub struct Operations {
     SHIFTLSB: bool,
-    sprite_load_address: u16
+    sprite_load_address: u16,
+
+    machine_code_policy: Policy,
+    unknown_policy: Policy,
+
+    // opcodes and addresses Policy::Ignore already logged, a loop would repeat them every frame
+    ignored: HashSet<(u16, u16)>
 }
 
 // Implement function new returning a new instance
 impl Operations {
-    
+
     /**
-     *  @func   new()   
+     *  @func   new()
+     *
+     *  @param  machine_code    policy for 0NNN
+     *
+     *  @param  unknown         policy for opcodes that can't be decoded
      * */
-    pub fn new(shift_lsb: bool, sprite_addr: u16) -> Operations {
+    pub fn new(shift_lsb: bool, sprite_addr: u16, machine_code: Policy, unknown: Policy) -> Operations {
         Operations {
             SHIFTLSB: shift_lsb,
-            sprite_load_address: sprite_addr
+            sprite_load_address: sprite_addr,
+
+            machine_code_policy: machine_code,
+            unknown_policy: unknown,
+
+            ignored: HashSet::new()
         }
     }
 
+    /**
+     *  @func   sprite_address()    address the font sprites are loaded to
+     * */
+    pub fn sprite_address(&self) -> u16 {
+        self.sprite_load_address
+    }
+
+    /**
+     *  @func   native_machine_code()   check if 0NNN runs on the CDP1802 core
+     * */
+    pub fn native_machine_code(&self) -> bool {
+        self.machine_code_policy == Policy::Native
+    }
+
     /**
     *  @opcode     0NNN    Call machine code routine at address NNN
+    *
+    *  without the CDP1802 core (Policy::Native, see Machine::execute()) the
+    *  machine code policy decides
     * */
-    pub fn call_machine_code(self,opc: u16) {
+    pub fn call_machine_code(&mut self, opc: u16, pmem: &mut Memory, preg: &mut Registers, trap: &mut Option<TrapHandler>) -> Result<(), Chip8Error> {
+        let halt = Chip8Error::MachineCodeCall { pc: preg.eip, addr: 0xFFF & opc };
+        apply_policy(self.machine_code_policy, halt, opc, pmem, preg, trap, &mut self.ignored)
+    }
 
+    /**
+    *  @opcode     ????    Opcode that can't be decoded, the unknown policy decides
+    * */
+    pub fn unknown_opcode(&mut self, opc: u16, pmem: &mut Memory, preg: &mut Registers, trap: &mut Option<TrapHandler>) -> Result<(), Chip8Error> {
+        let halt = Chip8Error::UnknownOpcode { pc: preg.eip, opcode: opc };
+        apply_policy(self.unknown_policy, halt, opc, pmem, preg, trap, &mut self.ignored)
     }
 
     /**
     *  @opcode     00E0    Clear display
     * */
-    pub fn clear_display(self) {
-        
+    pub fn clear_display(&self, fbuffer: &mut FBuffer) -> Result<(), Chip8Error> {
+        *fbuffer = [[0; HORIZONTAL]; VERTICAL];
+        Ok(())
     }
 
     /**
     *  @opcode     00EE    return from subroutine
     * */
-    pub fn return_from_call(self, mut pmem: Memory, mut preg: Registers) {
+    pub fn return_from_call(&self, pmem: &mut Memory, preg: &mut Registers) -> Result<(), Chip8Error> {
         // pop address from stack and restore the eip
-        let address = pmem.pop();
-        match address {
-            Some(addr)  => preg.eip = addr-0x02,    // for jump and such subtract 0x2 as the interpreter loop will increment eip after execution
-            None        => panic!("Error returning from call"),
-        } 
+        // the pushed eip points at the call, the interpreter loop will step past it
+        preg.eip = pmem.pop()?;
+        Ok(())
     }
 
     /**
     *  @opcode     1NNN    Jump to address NNN
     * */
-    pub fn jmp_address(self, opc: u16, mut preg: Registers) {
+    pub fn jmp_address(&self, opc: u16, preg: &mut Registers) -> Result<(), Chip8Error> {
         let addr = 0xFFF & opc;
-        // Implement Jump
-        preg.eip = addr-0x2;
+        // for jump and such subtract 0x2 as the interpreter loop will increment eip after execution
+        preg.eip = addr.wrapping_sub(0x2);
+        Ok(())
     }
 
     /**
-    *  @opcode     2NNN   Call subroutine at NNN 
+    *  @opcode     2NNN   Call subroutine at NNN
     * */
-    pub fn call_subroutine(self, opc: u16, mut pmem: Memory, mut preg: Registers) {
+    pub fn call_subroutine(&self, opc: u16, pmem: &mut Memory, preg: &mut Registers) -> Result<(), Chip8Error> {
         let addr = 0xFFF & opc;
-        // call subroutine 
+        // call subroutine
         // push current eip
-        pmem.push(preg.eip-0x2);
-        preg.eip = addr-0x2;
+        pmem.push(preg.eip)?;
+        preg.eip = addr.wrapping_sub(0x2);
+        Ok(())
     }
 
     /**
     *  @opcode     3XNN    Skips next instruction if VX == NN
     * */
-    pub fn reg_val_compare(self, opc: u16, mut preg: Registers) {
-        let reg_val = preg.rw_register(((0xF00 & opc) >> 8) as usize, MODE::READ).unwrap();
+    pub fn reg_val_compare(&self, opc: u16, preg: &mut Registers) -> Result<(), Chip8Error> {
+        let reg_val = preg.rw_register(((0xF00 & opc) >> 8) as usize, MODE::READ)?;
         let value: u8 = (0xFF & opc) as u8;
-        
+
         if reg_val == value {
-            preg.eip += 0x2;
+            preg.eip = preg.eip.wrapping_add(0x2);
         }
+        Ok(())
     }
     /**
     *  @opcode     4XNN    Skips next instruction if VX != NN
     *
     *  might be called redundant, but I like to keep my flags to a minimum
     * */
-    pub fn reg_val_noncompare(self, opc: u16, mut preg: Registers) {
-        let reg_val = preg.rw_register(((0xF00 & opc) >> 8) as usize, MODE::READ).unwrap();
+    pub fn reg_val_noncompare(&self, opc: u16, preg: &mut Registers) -> Result<(), Chip8Error> {
+        let reg_val = preg.rw_register(((0xF00 & opc) >> 8) as usize, MODE::READ)?;
         let value: u8 = (0xFF & opc) as u8;
 
         if reg_val != value {
-            preg.eip += 0x2; 
+            preg.eip = preg.eip.wrapping_add(0x2);
         }
+        Ok(())
     }
 
     /**
-    *  @opcode     5XY0    Skips next instruction if VX == VY 
+    *  @opcode     5XY0    Skips next instruction if VX == VY
     * */
-    pub fn reg_compare(self, opc: u16, mut preg: Registers) {
-        let reg_x = preg.rw_register(((0xF00 & opc) >> 8) as usize, MODE::READ).unwrap();
-        let reg_y = preg.rw_register(((0xF0 & opc) >> 4) as usize, MODE::READ).unwrap();
+    pub fn reg_compare(&self, opc: u16, preg: &mut Registers) -> Result<(), Chip8Error> {
+        let reg_x = preg.rw_register(((0xF00 & opc) >> 8) as usize, MODE::READ)?;
+        let reg_y = preg.rw_register(((0xF0 & opc) >> 4) as usize, MODE::READ)?;
 
         if reg_x == reg_y {
-            preg.eip  += 0x2;
+            preg.eip = preg.eip.wrapping_add(0x2);
         }
+        Ok(())
     }
 
     /**
     * @opcode      6XNN    Set VX to NN
     * */
-    pub fn reg_set(self, opc: u16, mut preg: Registers) {
+    pub fn reg_set(&self, opc: u16, preg: &mut Registers) -> Result<(), Chip8Error> {
         let reg_flag = ((0xF00 & opc) >> 8) as u8;
         let value: u8 = (0xFF & opc) as u8;
-        preg.rw_register(reg_flag as usize, MODE::WRITE(value));
+        preg.rw_register(reg_flag as usize, MODE::WRITE(value))?;
+        Ok(())
     }
 
     /**
-     * @opcode     7XNN     Add to VX
+     * @opcode     7XNN     Add to VX (carry flag is not changed)
      * */
-    pub fn reg_add(self, opc: u16, mut preg: Registers) {
-        let mut temp_val = preg.rw_register(((0xF00 & opc) >> 8) as usize, MODE::READ).unwrap();
-        temp_val += (0xFF & opc) as u8;
-        
-        preg.rw_register(((0xF00 & opc) >> 8) as usize, MODE::WRITE(temp_val));
+    pub fn reg_add(&self, opc: u16, preg: &mut Registers) -> Result<(), Chip8Error> {
+        let temp_val = preg.rw_register(((0xF00 & opc) >> 8) as usize, MODE::READ)?;
+
+        preg.rw_register(((0xF00 & opc) >> 8) as usize, MODE::WRITE(temp_val.wrapping_add((0xFF & opc) as u8)))?;
+        Ok(())
     }
 
     /**
-     *  @opcode     8XY0    set register value to other register value    
+     *  @opcode     8XY0    set register value to other register value
      * */
-    pub fn reg_assign(self, opc: u16, mut preg: Registers) {
-        let reg_y = preg.rw_register(((0xF0 & opc) >> 4) as usize, MODE::READ).unwrap();
-        
-        preg.rw_register(((0xF00 & opc) >> 8) as usize, MODE::WRITE(reg_y));
+    pub fn reg_assign(&self, opc: u16, preg: &mut Registers) -> Result<(), Chip8Error> {
+        let reg_y = preg.rw_register(((0xF0 & opc) >> 4) as usize, MODE::READ)?;
+
+        preg.rw_register(((0xF00 & opc) >> 8) as usize, MODE::WRITE(reg_y))?;
+        Ok(())
     }
 
     /**
      *  @opcode     8XY1    set VX to VX | VY
      * */
-    pub fn reg_or(self, opc: u16, mut preg: Registers) {
-        let reg_x = preg.rw_register(((0xF00 & opc) >> 8) as usize, MODE::READ).unwrap();
-        let reg_y = preg.rw_register(((0xF0 & opc) >> 4) as usize, MODE::READ).unwrap();
+    pub fn reg_or(&self, opc: u16, preg: &mut Registers) -> Result<(), Chip8Error> {
+        let reg_x = preg.rw_register(((0xF00 & opc) >> 8) as usize, MODE::READ)?;
+        let reg_y = preg.rw_register(((0xF0 & opc) >> 4) as usize, MODE::READ)?;
 
-        preg.rw_register(((0xF00 & opc) >> 8 ) as usize, MODE::WRITE(reg_x | reg_y));
+        preg.rw_register(((0xF00 & opc) >> 8 ) as usize, MODE::WRITE(reg_x | reg_y))?;
+        Ok(())
     }
 
     /**
      *  @opcode     8XY2    set Vx to VX & VY
      * */
-    pub fn reg_and(self, opc: u16, mut preg: Registers) {
-        let reg_x = preg.rw_register(((0xF00 & opc) >> 8) as usize, MODE::READ).unwrap();
-        let reg_y = preg.rw_register(((0xF0 & opc) >> 4) as usize, MODE::READ).unwrap();
+    pub fn reg_and(&self, opc: u16, preg: &mut Registers) -> Result<(), Chip8Error> {
+        let reg_x = preg.rw_register(((0xF00 & opc) >> 8) as usize, MODE::READ)?;
+        let reg_y = preg.rw_register(((0xF0 & opc) >> 4) as usize, MODE::READ)?;
 
-        preg.rw_register(((0xF00 & opc) >> 8) as usize, MODE::WRITE(reg_x & reg_y));
+        preg.rw_register(((0xF00 & opc) >> 8) as usize, MODE::WRITE(reg_x & reg_y))?;
+        Ok(())
     }
 
     /**
-     *  @opcode     8XY3    set VX to VX ^ VY   
+     *  @opcode     8XY3    set VX to VX ^ VY
      * */
-    pub fn reg_xor(self, opc: u16, mut preg: Registers) {
-        let reg_x = preg.rw_register(((0xF00 & opc) >> 8) as usize, MODE::READ).unwrap();
-        let reg_y = preg.rw_register(((0xF0 & opc) >> 4) as usize, MODE::READ).unwrap();
+    pub fn reg_xor(&self, opc: u16, preg: &mut Registers) -> Result<(), Chip8Error> {
+        let reg_x = preg.rw_register(((0xF00 & opc) >> 8) as usize, MODE::READ)?;
+        let reg_y = preg.rw_register(((0xF0 & opc) >> 4) as usize, MODE::READ)?;
 
-        preg.rw_register(((0xF00 & opc) >> 8) as usize, MODE::WRITE(reg_x ^ reg_y));
+        preg.rw_register(((0xF00 & opc) >> 8) as usize, MODE::WRITE(reg_x ^ reg_y))?;
+        Ok(())
     }
 
     /**
-     *  @opcode     8XY4    set VX to VX + VY
+     *  @opcode     8XY4    set VX to VX + VY -> on carry set VF to 1
      * */
-    pub fn reg_add_reg(self, opc: u16, mut preg: Registers) {
-        let reg_x = preg.rw_register(((0xF00 & opc) >> 8) as usize, MODE::READ).unwrap();
-        let reg_y = preg.rw_register(((0xF0 & opc) >> 4) as usize, MODE::READ).unwrap();
-        
-        // set carry (VF = 1)
-        if ((((0xF0 & reg_x) >> 4) + ((0xF0 & reg_y) >> 4)) > 0xF) || ((((0xF00 & reg_x) >> 8) + ((0xF00 & reg_y) >> 8)) > 0xF) {
-            preg.rw_register(0xF as usize, MODE::WRITE(0x1));
-        } else {
-            preg.rw_register(0xF as usize, MODE::WRITE(0x0));
-        }
+    pub fn reg_add_reg(&self, opc: u16, preg: &mut Registers) -> Result<(), Chip8Error> {
+        let reg_x = preg.rw_register(((0xF00 & opc) >> 8) as usize, MODE::READ)?;
+        let reg_y = preg.rw_register(((0xF0 & opc) >> 4) as usize, MODE::READ)?;
+
+        let (result, carry) = reg_x.overflowing_add(reg_y);
 
-        preg.rw_register(((0xF00 & opc) >> 8) as usize, MODE::WRITE(reg_x + reg_y));
+        // flag is written last, so it wins if X is F
+        preg.rw_register(((0xF00 & opc) >> 8) as usize, MODE::WRITE(result))?;
+        preg.rw_register(0xF, MODE::WRITE(carry as u8))?;
+        Ok(())
     }
 
     /**
-     *  @opcode     8XY5    set VX to VX - VY 
+     *  @opcode     8XY5    set VX to VX - VY -> on borrow set VF to 0
      * */
-    pub fn regx_sub_regy(self, opc: u16, mut preg: Registers) {
-        let reg_x = preg.rw_register(((0xF00 & opc) >> 8) as usize, MODE::READ).unwrap();
-        let reg_y = preg.rw_register(((0xF0 & opc) >> 4) as usize, MODE::READ).unwrap();
-        
-        if ((((0xF0 & reg_x) >> 4) - ((0xF0 & reg_y) >> 4)) < 0x0) || ((((0xF00 & reg_x) >> 8) - ((0xF00 & reg_y) >> 8)) < 0x0) {
-            preg.rw_register(0xF as usize, MODE::WRITE(0x1));
-        } else {
-            preg.rw_register(0xF as usize, MODE::WRITE(0x0));
-        }
+    pub fn regx_sub_regy(&self, opc: u16, preg: &mut Registers) -> Result<(), Chip8Error> {
+        let reg_x = preg.rw_register(((0xF00 & opc) >> 8) as usize, MODE::READ)?;
+        let reg_y = preg.rw_register(((0xF0 & opc) >> 4) as usize, MODE::READ)?;
 
-        preg.rw_register(((0xF00 & opc) >> 8) as usize, MODE::WRITE(reg_x-reg_y));
+        let (result, borrow) = reg_x.overflowing_sub(reg_y);
+
+        preg.rw_register(((0xF00 & opc) >> 8) as usize, MODE::WRITE(result))?;
+        preg.rw_register(0xF, MODE::WRITE(!borrow as u8))?;
+        Ok(())
     }
 
     /**
@@ -202,143 +299,262 @@ impl Operations {
      *                      true ) Or it stores the LSB of VY in VF before shifting right by one
      *                      and storing in the result in VX
      * */
-    pub fn lsb_shift_right(self, opc: u16, mut preg: Registers) {
-        if !self.SHIFTLSB {
-            let mut reg_x = preg.rw_register(((0xF00 & opc) >> 8) as usize, MODE::READ).unwrap();
-            reg_x = &reg_x >> 1;
-            preg.rw_register(((0xF00 & opc) >> 8) as usize, MODE::WRITE(reg_x));
+    pub fn lsb_shift_right(&self, opc: u16, preg: &mut Registers) -> Result<(), Chip8Error> {
+        let source = if !self.SHIFTLSB {
+            preg.rw_register(((0xF00 & opc) >> 8) as usize, MODE::READ)?
         } else {
-            let reg_y = preg.rw_register(((0xF0 & opc) >> 4) as usize, MODE::READ).unwrap();
-            
-        } 
+            preg.rw_register(((0xF0 & opc) >> 4) as usize, MODE::READ)?
+        };
+
+        preg.rw_register(((0xF00 & opc) >> 8) as usize, MODE::WRITE(source >> 1))?;
+        preg.rw_register(0xF, MODE::WRITE(source & 0x1))?;
+        Ok(())
     }
-    
+
     /**
-     *  @opcode     8XY7    Set VX to VX - VY -> on borrow set VF to 0
+     *  @opcode     8XY7    Set VX to VY - VX -> on borrow set VF to 0
      */
-    pub fn regy_sub_regx(self, opc: u16, mut preg: Registers) {
-        let reg_x = preg.rw_register(((0xF00 & opc) >> 8) as usize, MODE::READ).unwrap();
-        let reg_y = preg.rw_register(((0xF0 & opc) >> 4) as usize, MODE::READ).unwrap();
+    pub fn regy_sub_regx(&self, opc: u16, preg: &mut Registers) -> Result<(), Chip8Error> {
+        let reg_x = preg.rw_register(((0xF00 & opc) >> 8) as usize, MODE::READ)?;
+        let reg_y = preg.rw_register(((0xF0 & opc) >> 4) as usize, MODE::READ)?;
+
+        let (result, borrow) = reg_y.overflowing_sub(reg_x);
 
-        let result = 0;
+        preg.rw_register(((0xF00 & opc) >> 8) as usize, MODE::WRITE(result))?;
+        preg.rw_register(0xF, MODE::WRITE(!borrow as u8))?;
+        Ok(())
     }
 
     /**
-     *  @opcode     8XYE    
+     *  @opcode     8XYE    same as 8XY6 but shifts left and stores the MSB in VF
      */
-    pub fn lsb_shift_left(self, opc: u16, mut preg: Registers) {
+    pub fn lsb_shift_left(&self, opc: u16, preg: &mut Registers) -> Result<(), Chip8Error> {
+        let source = if !self.SHIFTLSB {
+            preg.rw_register(((0xF00 & opc) >> 8) as usize, MODE::READ)?
+        } else {
+            preg.rw_register(((0xF0 & opc) >> 4) as usize, MODE::READ)?
+        };
 
+        preg.rw_register(((0xF00 & opc) >> 8) as usize, MODE::WRITE(source << 1))?;
+        preg.rw_register(0xF, MODE::WRITE(source >> 7))?;
+        Ok(())
     }
 
     /**
-     *  @opcode     9XY0    
+     *  @opcode     9XY0    Skips next instruction if VX != VY
      */
-    pub fn reg_noncompare(self, opc: u16, mut preg: Registers) {
+    pub fn reg_noncompare(&self, opc: u16, preg: &mut Registers) -> Result<(), Chip8Error> {
+        let reg_x = preg.rw_register(((0xF00 & opc) >> 8) as usize, MODE::READ)?;
+        let reg_y = preg.rw_register(((0xF0 & opc) >> 4) as usize, MODE::READ)?;
 
+        if reg_x != reg_y {
+            preg.eip = preg.eip.wrapping_add(0x2);
+        }
+        Ok(())
     }
 
     /**
-     *  @opcode     ANNN    
+     *  @opcode     ANNN    Set I to NNN
      */
-    pub fn jmp_I(self, opc: u16, mut preg: Registers) {
-
+    pub fn jmp_I(&self, opc: u16, preg: &mut Registers) -> Result<(), Chip8Error> {
+        preg.address_register = 0xFFF & opc;
+        Ok(())
     }
 
     /**
-     *  @opcode     BNNN    
+     *  @opcode     BNNN    Jump to address NNN + V0
      */
-    pub fn jmp_offset(self, opc: u16, mut preg: Registers) {
+    pub fn jmp_offset(&self, opc: u16, preg: &mut Registers) -> Result<(), Chip8Error> {
+        let offset = preg.rw_register(0x0, MODE::READ)? as u16;
 
+        preg.eip = ((0xFFF & opc) + offset).wrapping_sub(0x2);
+        Ok(())
     }
 
     /**
-     *  @opcode     CXNN
+     *  @opcode     CXNN    Set VX to a random byte & NN
      */
-    pub fn rand_reg(self, opc: u16, mut preg: Registers) {
+    pub fn rand_reg(&self, opc: u16, pmem: &mut Memory, preg: &mut Registers, rng: &mut Rng) -> Result<(), Chip8Error> {
+        let value = rng.next_byte(pmem)? & (0xFF & opc) as u8;
 
+        preg.rw_register(((0xF00 & opc) >> 8) as usize, MODE::WRITE(value))?;
+        Ok(())
     }
 
     /**
-     *  @opcode     DXYN    
+     *  @opcode     DXYN    Draw N bytes starting at I as sprite at (VX, VY)
+     *                      VF is set to 1 if a set pixel is erased
      */
-    pub fn draw_sprite(self, opc: u16, mut preg: Registers) {
+    pub fn draw_sprite(&self, opc: u16, pmem: &mut Memory, preg: &mut Registers, fbuffer: &mut FBuffer) -> Result<(), Chip8Error> {
+        let x = preg.rw_register(((0xF00 & opc) >> 8) as usize, MODE::READ)? as usize % HORIZONTAL;
+        let y = preg.rw_register(((0xF0 & opc) >> 4) as usize, MODE::READ)? as usize % VERTICAL;
+        let height = (0xF & opc) as usize;
+
+        let mut collision = 0x0;
+
+        for row in 0..height {
+            let sprite_line = pmem.rw_memory(preg.address_register as usize + row, MODE::READ)?;
+
+            for bit in 0..8 {
+                // sprites are clipped at the screen edges
+                if x + bit >= HORIZONTAL || y + row >= VERTICAL {
+                    continue;
+                }
+
+                if sprite_line & (0x80 >> bit) != 0 {
+                    let pixel = &mut fbuffer[y + row][x + bit];
+                    if *pixel == 1 {
+                        collision = 0x1;
+                    }
+                    *pixel ^= 1;
+                }
+            }
+        }
 
+        preg.rw_register(0xF, MODE::WRITE(collision))?;
+        Ok(())
     }
 
     /**
-     *  @opcode     EX9E
+     *  @opcode     EX9E    Skips next instruction if key VX is pressed
      */
-    pub fn stored_key_pressed(self, opc: u16, mut preg: Registers) {
+    pub fn stored_key_pressed(&self, opc: u16, preg: &mut Registers, keypad: &Keypad) -> Result<(), Chip8Error> {
+        let key = preg.rw_register(((0xF00 & opc) >> 8) as usize, MODE::READ)?;
 
+        if keypad.is_pressed(key) {
+            preg.eip = preg.eip.wrapping_add(0x2);
+        }
+        Ok(())
     }
 
     /**
-     *  @opcode     EXA1
+     *  @opcode     EXA1    Skips next instruction if key VX is not pressed
      */
-    pub fn stored_key_notpressed(self, opc: u16) {
+    pub fn stored_key_notpressed(&self, opc: u16, preg: &mut Registers, keypad: &Keypad) -> Result<(), Chip8Error> {
+        let key = preg.rw_register(((0xF00 & opc) >> 8) as usize, MODE::READ)?;
 
+        if !keypad.is_pressed(key) {
+            preg.eip = preg.eip.wrapping_add(0x2);
+        }
+        Ok(())
     }
 
     /**
-     *  @opcode     FX07
+     *  @opcode     FX07    Set VX to the delay timer
      */
-    pub fn get_delay(self, opc: u16) {
-
+    pub fn get_delay(&self, opc: u16, preg: &mut Registers) -> Result<(), Chip8Error> {
+        let delay = preg.delay_timer;
+        preg.rw_register(((0xF00 & opc) >> 8) as usize, MODE::WRITE(delay))?;
+        Ok(())
     }
 
     /**
-     *  @opcode     FX0A
+     *  @opcode     FX0A    Wait for a key press and store it in VX
      */
-    pub fn await_press(opc: u16) {
-
+    pub fn await_press(&self, opc: u16, preg: &mut Registers, keypad: &Keypad) -> Result<(), Chip8Error> {
+        match keypad.first_pressed() {
+            Some(key)   => {
+                preg.rw_register(((0xF00 & opc) >> 8) as usize, MODE::WRITE(key))?;
+            },
+            // repeat this instruction until a key is pressed
+            None        => preg.eip = preg.eip.wrapping_sub(0x2),
+        }
+        Ok(())
     }
 
     /**
-     *  @opcode     FX15
+     *  @opcode     FX15    Set the delay timer to VX
      */
-    pub fn set_delay_timer(opc: u16) {
-
+    pub fn set_delay_timer(&self, opc: u16, preg: &mut Registers) -> Result<(), Chip8Error> {
+        preg.delay_timer = preg.rw_register(((0xF00 & opc) >> 8) as usize, MODE::READ)?;
+        Ok(())
     }
 
     /**
-     *  @opcode     FX18
+     *  @opcode     FX18    Set the sound timer to VX
      */
-    pub fn set_sound_timer(opc: u16) {
-
+    pub fn set_sound_timer(&self, opc: u16, preg: &mut Registers) -> Result<(), Chip8Error> {
+        preg.sound_timer = preg.rw_register(((0xF00 & opc) >> 8) as usize, MODE::READ)?;
+        Ok(())
     }
 
     /**
-     *  @opcode     FX1E
+     *  @opcode     FX1E    Add VX to I
      */
-    pub fn reg_add_I(opc: u16) {
-
+    pub fn reg_add_I(&self, opc: u16, preg: &mut Registers) -> Result<(), Chip8Error> {
+        let reg_x = preg.rw_register(((0xF00 & opc) >> 8) as usize, MODE::READ)?;
+        preg.address_register = preg.address_register.wrapping_add(reg_x as u16);
+        Ok(())
     }
 
     /**
-     *  @opcode     FX29
+     *  @opcode     FX29    Set I to the location of the font sprite for the digit in VX
      */
-    pub fn set_I_sprite_reg(opc: u16, sprite_address: u16) {
-
+    pub fn set_I_sprite_reg(&self, opc: u16, preg: &mut Registers) -> Result<(), Chip8Error> {
+        let digit = preg.rw_register(((0xF00 & opc) >> 8) as usize, MODE::READ)? & 0xF;
+        // every digit is 5 bytes tall
+        preg.address_register = self.sprite_load_address + (digit as u16) * 5;
+        Ok(())
     }
 
     /**
-     *  @opcode     FX33
+     *  @opcode     FX33    Store the BCD representation of VX at I, I+1, I+2
      */
-    pub fn store_bcd_at_I(opc: u16) {
+    pub fn store_bcd_at_I(&self, opc: u16, pmem: &mut Memory, preg: &mut Registers) -> Result<(), Chip8Error> {
+        let reg_x = preg.rw_register(((0xF00 & opc) >> 8) as usize, MODE::READ)?;
+        let addr = preg.address_register as usize;
 
+        pmem.rw_memory(addr, MODE::WRITE(reg_x / 100))?;
+        pmem.rw_memory(addr + 1, MODE::WRITE((reg_x / 10) % 10))?;
+        pmem.rw_memory(addr + 2, MODE::WRITE(reg_x % 10))?;
+        Ok(())
     }
 
     /**
-     *  @opcode     FX55
+     *  @opcode     FX55    Store V0 to VX in memory starting at I
      */
-    pub fn write_reg_mem(opc: u16) {
+    pub fn write_reg_mem(&self, opc: u16, pmem: &mut Memory, preg: &mut Registers) -> Result<(), Chip8Error> {
+        let last = ((0xF00 & opc) >> 8) as usize;
 
+        for n in 0..=last {
+            let value = preg.rw_register(n, MODE::READ)?;
+            pmem.rw_memory(preg.address_register as usize + n, MODE::WRITE(value))?;
+        }
+        Ok(())
     }
 
     /**
-     *  @opcode     FX65
+     *  @opcode     FX65    Fill V0 to VX from memory starting at I
      */
-    pub fn read_reg_mem(opc: u16) {
+    pub fn read_reg_mem(&self, opc: u16, pmem: &mut Memory, preg: &mut Registers) -> Result<(), Chip8Error> {
+        let last = ((0xF00 & opc) >> 8) as usize;
+
+        for n in 0..=last {
+            let value = pmem.rw_memory(preg.address_register as usize + n, MODE::READ)?;
+            preg.rw_register(n, MODE::WRITE(value))?;
+        }
+        Ok(())
+    }
+}
 
+/**
+ *  @func   apply_policy()  halt, ignore or trap an instruction the interpreter can't execute
+ *
+ *  @param  halt            error returned by Policy::Halt
+ *
+ *  @param  ignored         instructions Policy::Ignore logged so far, each is logged once
+ * */
+fn apply_policy(policy: Policy, halt: Chip8Error, opc: u16, pmem: &mut Memory, preg: &mut Registers, trap: &mut Option<TrapHandler>, ignored: &mut HashSet<(u16, u16)>) -> Result<(), Chip8Error> {
+    match (policy, trap) {
+        (Policy::Halt, _)               => Err(halt),
+        (Policy::Ignore, _)             => {
+            if ignored.insert((opc, preg.eip)) {
+                eprintln!("Ignored: {}", halt);
+            }
+            Ok(())
+        },
+        (Policy::Trap, Some(handler))   => handler(opc, pmem, preg),
+        (Policy::Trap, None)            => Err(halt),
+        (Policy::Native, _)             => Err(halt),
     }
 }
@@ -0,0 +1,96 @@
+use std::io;
+
+/**
+ *  Platforms a ROM can be written for
+ *  - Vip       COSMAC VIP, the original interpreter
+ *  - Schip     SUPER-CHIP 1.1 on the HP48
+ *  - XoChip    XO-CHIP as implemented by Octo
+ *
+ *  Every platform runs the instructions of the ones before it.
+ * */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Platform {
+    Vip,
+    Schip,
+    XoChip
+}
+
+impl Platform {
+    /**
+     *  @func   from_name()     platform by its commandline name
+     *
+     *  @param  name            "vip", "schip" or "xochip"
+     * */
+    pub fn from_name(name: &str) -> Result<Platform, io::Error> {
+        match name {
+            "vip"       => Ok(Platform::Vip),
+            "schip"     => Ok(Platform::Schip),
+            "xochip"    => Ok(Platform::XoChip),
+            _           => Err(io::Error::new(io::ErrorKind::InvalidInput, "Unknown platform")),
+        }
+    }
+
+    /**
+     *  @func   name()          commandline name of the platform
+     * */
+    pub fn name(self) -> &'static str {
+        match self {
+            Platform::Vip       => "vip",
+            Platform::Schip     => "schip",
+            Platform::XoChip    => "xochip",
+        }
+    }
+
+    /**
+     *  @func   stack_depth()   number of return addresses the interpreter's stack holds
+     * */
+    pub fn stack_depth(self) -> usize {
+        match self {
+            Platform::Vip       => 12,
+            Platform::Schip     => 16,
+            Platform::XoChip    => 16,
+        }
+    }
+
+    /**
+     *  @func   shift_lsb()     8XY6/8XYE shift VY into VX (true) or VX in place (false)
+     *
+     *  SUPER-CHIP on the HP48 shifts in place, Octo's XO-CHIP mode kept the VIP's behaviour
+     * */
+    pub fn shift_lsb(self) -> bool {
+        match self {
+            Platform::Vip       => true,
+            Platform::Schip     => false,
+            Platform::XoChip    => true,
+        }
+    }
+
+    /**
+     *  @func   program_end()   address + 1 a program may fill up to
+     *
+     *  The VIP's interpreter keeps its stack, variables and display above 0xEA0,
+     *  SUPER-CHIP leaves all of 4K to the program and XO-CHIP all of 64K.
+     * */
+    pub fn program_end(self) -> usize {
+        match self {
+            Platform::Vip       => 0xEA0,
+            Platform::Schip     => 0x1000,
+            Platform::XoChip    => 0x10000,
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quirk_defaults() {
+        let quirks = |name| {
+            let platform = Platform::from_name(name).unwrap();
+            (platform.shift_lsb(), platform.stack_depth())
+        };
+        assert_eq!(quirks("vip"), (true, 12));
+        assert_eq!(quirks("schip"), (false, 16));
+        assert_eq!(quirks("xochip"), (true, 16));
+    }
+}
@@ -0,0 +1,88 @@
+use std::io;
+
+use super::super::Drivers::memory::{Memory, MODE};
+use super::super::error::Chip8Error;
+
+/**
+ *  Random number source used by CXNN
+ *
+ *  - Xorshift  xorshift64* seeded through splitmix64, fast and reproducible
+ *  - Vip       the COSMAC VIP interpreter routine
+ *
+ *  The VIP routine mixes a 16 bit counter (R9) with bytes read from the
+ *  interpreter page 0x0100-0x01FF, so the numbers only match the original
+ *  if that page holds the interpreter.
+ * */
+#[derive(Debug, Clone, PartialEq)]
+pub enum Rng {
+    Xorshift(u64),
+    Vip(u16)
+}
+
+impl Rng {
+    /**
+     *  @func   new()       create a random number source
+     *
+     *  @param  mode        "xorshift" or "vip"
+     *
+     *  @param  seed        initial state, the VIP routine only uses the lower 16 bits
+     * */
+    pub fn new(mode: &str, seed: u64) -> Result<Rng, io::Error> {
+        match mode {
+            "xorshift"  => Ok(Rng::Xorshift(splitmix64(seed))),
+            "vip"       => Ok(Rng::Vip(seed as u16)),
+            _           => Err(io::Error::new(io::ErrorKind::InvalidInput, "Unknown random number generator")),
+        }
+    }
+
+    /**
+     *  @func   state()     current generator state, part of the machine state
+     * */
+    pub fn state(&self) -> u64 {
+        match self {
+            Rng::Xorshift(state)    => *state,
+            Rng::Vip(r9)            => *r9 as u64,
+        }
+    }
+
+    /**
+     *  @func   next_byte()     advance the generator and return a random byte
+     *
+     *  @param  pmem            memory, read by the VIP routine
+     * */
+    pub fn next_byte(&mut self, pmem: &mut Memory) -> Result<u8, Chip8Error> {
+        match self {
+            Rng::Xorshift(state) => {
+                *state ^= *state >> 12;
+                *state ^= *state << 25;
+                *state ^= *state >> 27;
+                Ok((state.wrapping_mul(0x2545F4914F6CDD1D) >> 56) as u8)
+            },
+            Rng::Vip(r9) => {
+                // INC R9, then add the interpreter byte at 01(R9.0) to R9.1
+                *r9 = r9.wrapping_add(1);
+                let code = pmem.rw_memory(0x0100 | (*r9 & 0xFF) as usize, MODE::READ)?;
+                let (sum, carry) = code.overflowing_add((*r9 >> 8) as u8);
+
+                // SHRC rotates the carry of the addition into bit 7, then ADD the previous sum
+                let shifted = (sum >> 1) | ((carry as u8) << 7);
+                let result = shifted.wrapping_add(sum);
+
+                *r9 = ((result as u16) << 8) | (*r9 & 0xFF);
+                Ok(result)
+            },
+        }
+    }
+}
+
+/**
+ *  @func   splitmix64()    spread a seed over all 64 bits, xorshift must never start at 0
+ * */
+fn splitmix64(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9E3779B97F4A7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^= z >> 31;
+
+    if z == 0 { 0x9E3779B97F4A7C15 } else { z }
+}
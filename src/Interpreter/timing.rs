@@ -0,0 +1,101 @@
+use std::io;
+
+use super::cdp1802::CYCLES_PER_FRAME;
+
+// machine cycles the CDP1861 takes per frame: 128 lines of 8 DMA cycles plus the interrupt routine
+const VIDEO_CYCLES: u64 = 128 * 8 + 46;
+
+// machine cycles left for the interpreter between two video interrupts
+pub const VIP_CYCLES_PER_FRAME: u64 = CYCLES_PER_FRAME - VIDEO_CYCLES;
+
+// fetching and decoding an instruction in the interpreter's main loop
+const FETCH_CYCLES: u64 = 40;
+
+// a taken skip jumps over the next instruction
+const SKIP_CYCLES: u64 = 4;
+
+/**
+ *  How much of the program runs per 60Hz frame
+ *  - Ipf       a fixed number of instructions per frame
+ *  - Vip       each instruction costs its machine cycles on the COSMAC VIP
+ *
+ *  With Vip timing DXYN waits for the display interrupt and the timers tick from it.
+ * */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Timing {
+    Ipf,
+    Vip
+}
+
+impl Timing {
+    /**
+     *  @func   from_name()     timing model by its commandline name
+     *
+     *  @param  name            "ipf" or "vip"
+     * */
+    pub fn from_name(name: &str) -> Result<Timing, io::Error> {
+        match name {
+            "ipf"       => Ok(Timing::Ipf),
+            "vip"       => Ok(Timing::Vip),
+            _           => Err(io::Error::new(io::ErrorKind::InvalidInput, "Unknown timing model")),
+        }
+    }
+
+    /**
+     *  @func   name()      commandline name of the timing model
+     * */
+    pub fn name(self) -> &'static str {
+        match self {
+            Timing::Ipf     => "ipf",
+            Timing::Vip     => "vip",
+        }
+    }
+}
+
+/**
+ *  @func   vip_cycles()    machine cycles an instruction takes in the VIP interpreter
+ *
+ *  @param  opc             executed instruction
+ *
+ *  @param  vx              value of VX before the instruction ran
+ *
+ *  @param  skipped         a skip instruction skipped
+ *
+ *  The counts approximate the interpreter's routines including their data
+ *  dependent parts (sprite alignment, BCD digits, register count).
+ * */
+pub fn vip_cycles(opc: u16, vx: u8, skipped: bool) -> u64 {
+    let x = ((opc & 0x0F00) >> 8) as u64;
+    let n = (opc & 0x000F) as u64;
+
+    let cycles = match opc & 0xF000 {
+        0x0000 => match opc {
+            0x00E0  => 24 + 3078,
+            0x00EE  => 10,
+            _       => 12,
+        },
+        0x1000  => 12,
+        0x2000  => 26,
+        0x3000 | 0x4000 => 10,
+        0x5000 | 0x9000 => 14,
+        0x6000  => 6,
+        0x7000  => 10,
+        0x8000  => if n == 0 { 12 } else { 44 },
+        0xA000  => 12,
+        0xB000  => 22,
+        0xC000  => 36,
+        // unaligned sprites are shifted across two display bytes
+        0xD000  => 26 + n * if vx.is_multiple_of(8) { 46 } else { 68 },
+        0xE000  => 14,
+        _ => match opc & 0x00FF {
+            0x07 | 0x0A | 0x15 | 0x18 => 10,
+            0x1E | 0x29 => 16,
+            // BCD counts down each digit
+            0x33    => 80 + 16 * (vx / 100 + vx / 10 % 10 + vx % 10) as u64,
+            0x55 | 0x65 => 14 + 14 * (x + 1),
+            _       => 12,
+        },
+    };
+
+    FETCH_CYCLES + cycles + if skipped { SKIP_CYCLES } else { 0 }
+}
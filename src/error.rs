@@ -0,0 +1,140 @@
+/*
+ *  ===========================================================
+ *
+ *     Filename:    error.rs
+ *  Description:    errors raised while loading and running a
+ *                  rom
+ *
+ *  ===========================================================
+ * */
+
+use std::error;
+use std::fmt;
+use std::io;
+
+use super::Drivers::memory::{FaultKind, Region};
+
+/**
+ *  Everything that can stop the machine
+ *
+ *  pc is the address of the instruction that caused the fault. Memory and
+ *  stack faults are raised by Memory, which does not know the instruction,
+ *  so they start out as None and step() fills them in (see with_pc()).
+ * */
+#[derive(Debug)]
+pub enum Chip8Error {
+    UnknownOpcode { pc: u16, opcode: u16 },
+    MachineCodeCall { pc: u16, addr: u16 },
+    MachineCodeTimeout { pc: u16, addr: u16 },
+    StackOverflow { pc: Option<u16>, stack: Vec<u16> },
+    StackUnderflow { pc: Option<u16> },
+    MemoryFault { pc: Option<u16>, addr: usize, kind: FaultKind },
+    RomEmpty,
+    RomTooLarge { size: usize, max: usize },
+    FileTooLarge { max: usize },
+    RomUnreadable { name: String, err: io::Error },
+    Io(io::Error)
+}
+
+impl Chip8Error {
+    /**
+     *  @func   with_pc()   attach the address of the faulting instruction
+     *
+     *  @param  eip         address of the instruction being executed
+     * */
+    pub fn with_pc(mut self, eip: u16) -> Chip8Error {
+        match &mut self {
+            Chip8Error::StackOverflow { pc, .. }
+            | Chip8Error::StackUnderflow { pc }
+            | Chip8Error::MemoryFault { pc, .. } => {
+                pc.get_or_insert(eip);
+            },
+            _ => {},
+        }
+        self
+    }
+
+    /**
+     *  @func   is_fault()  the machine stopped on an instruction, not the host on loading or I/O
+     * */
+    pub fn is_fault(&self) -> bool {
+        matches!(self, Chip8Error::UnknownOpcode { .. }
+            | Chip8Error::MachineCodeCall { .. }
+            | Chip8Error::MachineCodeTimeout { .. }
+            | Chip8Error::StackOverflow { .. }
+            | Chip8Error::StackUnderflow { .. }
+            | Chip8Error::MemoryFault { .. })
+    }
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Chip8Error::UnknownOpcode { pc, opcode } => {
+                return write!(f, "Unknown opcode {:04X} at PC {:#05x}", opcode, pc);
+            },
+            Chip8Error::MachineCodeCall { pc, addr } => {
+                return write!(f, "Machine code call to {:#05x} at PC {:#05x}", addr, pc);
+            },
+            Chip8Error::MachineCodeTimeout { pc, addr } => {
+                return write!(f, "Machine code at {:#05x} did not return (SEP R4) at PC {:#05x}", addr, pc);
+            },
+            Chip8Error::StackOverflow { stack, .. } => {
+                write!(f, "Stack overflow: call with {} return addresses on the stack", stack.len())?;
+            },
+            Chip8Error::StackUnderflow { .. } => {
+                write!(f, "Stack underflow: return with an empty stack")?;
+            },
+            Chip8Error::MemoryFault { addr, kind: FaultKind::OutOfBounds, .. } => {
+                write!(f, "Memory fault: access to {:#05x} is out of bounds", addr)?;
+            },
+            Chip8Error::MemoryFault { addr, kind: FaultKind::WriteProtected, .. } => {
+                write!(f, "Memory fault: write to {:#05x} ({}) is protected", addr,
+                    Region::of(*addr).unwrap_or(Region::Interpreter))?;
+            },
+            Chip8Error::RomEmpty => {
+                return write!(f, "Rom is empty");
+            },
+            Chip8Error::FileTooLarge { max } => {
+                return write!(f, "Rom file is larger than {} bytes", max);
+            },
+            Chip8Error::RomUnreadable { name, err } => {
+                return write!(f, "Can't read rom {}: {}", name, err);
+            },
+            Chip8Error::RomTooLarge { size, max } => {
+                return write!(f, "Rom is {} bytes, only {} bytes fit into memory", size, max);
+            },
+            Chip8Error::Io(err) => {
+                return write!(f, "{}", err);
+            },
+        }
+
+        match self {
+            Chip8Error::StackOverflow { pc: Some(pc), .. }
+            | Chip8Error::StackUnderflow { pc: Some(pc) }
+            | Chip8Error::MemoryFault { pc: Some(pc), .. } => write!(f, " at PC {:#05x}", pc)?,
+            _ => {},
+        }
+
+        if let Chip8Error::StackOverflow { stack, .. } = self {
+            write!(f, "\nStack: {:03x?}", stack)?;
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for Chip8Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Chip8Error::Io(err)                     => Some(err),
+            Chip8Error::RomUnreadable { err, .. }   => Some(err),
+            _                                       => None,
+        }
+    }
+}
+
+impl From<io::Error> for Chip8Error {
+    fn from(err: io::Error) -> Chip8Error {
+        Chip8Error::Io(err)
+    }
+}
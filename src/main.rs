@@ -15,227 +15,597 @@
 * =====================================================================================
 */
 
+#![allow(non_snake_case, clippy::upper_case_acronyms)]
+
 mod Drivers;
 mod Interpreter;
+mod error;
 
-use Drivers::{file_io};
+use Drivers::file_io::{self, Source};
+use Drivers::capture::Capture;
+use Drivers::container;
+use Drivers::database::{self, Database};
+use Drivers::export::Export;
+use Drivers::filter::Filter;
+use Drivers::frontend::{Frontend, Headless};
+use Drivers::speed::{Frames, Speed, RATE};
 use Drivers::display::*;
-use Drivers::memory::{Memory, Registers};
+use Drivers::image::Image;
+use Drivers::keyboard_io::{KeyMap, Keypad};
+use Drivers::hexfile::{self, HexFormat};
+use Drivers::memory::{Memory, Registers, MEMSIZE};
+use Drivers::movie::{Movie, MovieHeader, MoviePlayer, MovieRecorder};
+use Drivers::palette::Palette;
+use Drivers::phosphor::{Blend, Phosphor, Shades};
+use Drivers::terminal::{Cells, Terminal};
+use Drivers::timer::Timer;
+use Interpreter::analysis::analyze;
+use Interpreter::machine::Machine;
 use Interpreter::opcode::*;
+use Interpreter::platform::Platform;
+use Interpreter::rng::Rng;
+use Interpreter::timing::Timing;
+use error::Chip8Error;
+use std::env;
+use std::fs;
 use std::io;
+use std::process;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
-// TODO: Add commandline input for e.g. ENTRY Address, command options, rom file
+// TODO: Add commandline input for e.g. ENTRY Address
 
 const ENTRY: u16 = 0x200;
-const SPRITEENTRY: u16 = 0x200;
-const SHIFTLSB: bool = true;
+const SPRITEENTRY: u16 = 0x050;
 const IMAGE: &str = "cavern.ch8";
+const IPF: u32 = 10;
+const TIMING: Timing = Timing::Ipf;
+const SCALE: usize = 1;
+const VIEWPORT: Viewport = Viewport::Integer;
+const HASH_INTERVAL: u64 = 60;
+const RNG: &str = "xorshift";
+const PLATFORM: Platform = Platform::Vip;
+const MACHINE_CODE_POLICY: Policy = Policy::Ignore;
+const UNKNOWN_POLICY: Policy = Policy::Halt;
+
+const USAGE: &str = "usage: CHIP8 [rom|-] [--headless] [--terminal half|braille] [--frames N] [--screenshot-at-frame N file.png] [--scale N] [--theme classic|amber|green|lcd|contrast] [--colors off,on[,plane2[,both]]] [--phosphor off|max|N] [--viewport integer|fit] [--filter nearest|scale2x|scale3x|scanlines|crt] [--export file.y4m|file.ppm|file.pbm] [--record movie|file.gif|file.png] [--play movie] [--hash-interval N] [--seed N] [--rng xorshift|vip] [--ipf N] [--speed 1/4|1/2|1|2|4|8|max] [--title name] [--keys up=K,down=K,left=K,right=K,a=K,b=K] [--database file.json] [--timing ipf|vip] [--protect-font] [--shift-quirk] [--platform vip|schip|xochip] [--stack-depth N] [--on-machine-code halt|ignore|trap|native] [--on-unknown halt|ignore|trap] [--boot interpreter] [--dump file.hex|file.srec] [--dump-range from-to] [--config file]\n       CHIP8 info rom|- [--database file.json]";
+
+/**
+ *  Options given on the commandline
+ * */
+struct Options {
+    image: String,
+    headless: bool,
+    terminal: Option<Cells>,
+    frames: Option<u64>,
+    screenshots: Vec<(u64, String)>,
+    scale: usize,
+    palette: Palette,
+    phosphor: Blend,
+    viewport: Viewport,
+    filter: Filter,
+    export: Option<String>,
+    record: Option<String>,
+    capture: Option<String>,
+    play: Option<String>,
+    hash_interval: u64,
+    seed: Option<u64>,
+    rng: String,
+    ipf: u32,
+    speed: Option<String>,
+    title: Option<String>,
+    keys: KeyMap,
+    databases: Vec<String>,
+    timing: Timing,
+    protect_font: bool,
+    shift_lsb: Option<bool>,
+    platform: Platform,
+    stack_depth: Option<usize>,
+    machine_code_policy: Policy,
+    unknown_policy: Policy,
+    boot: Option<String>,
+    dump: Option<(String, HexFormat)>,
+    dump_range: (usize, usize)
+}
 
 fn main() {
+    // print faults readable instead of the Debug output of returning them from main
+    if let Err(err) = start() {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+}
 
-    let states = init();
-    let mut mem: Memory = states.0;
-    let mut reg: Registers = states.1;
-    let opcodes: Operations = states.2; 
+/**
+ *  @func   start()     Set up the machine from the commandline and run it
+ */
+fn start() -> Result<(), Chip8Error> {
 
-    //some filehandling
-    let image = file_io::read_binary(IMAGE).unwrap();
-    println!("Size: {}", file_io::filesize(IMAGE).unwrap());
+    let args = expand_config(env::args().skip(1))?;
+    if args.first().is_some_and(|arg| arg == "info") {
+        return info(args.into_iter().skip(1));
+    }
+    let options = parse_args(args.iter().cloned())?;
+
+    let bytes = file_io::read(Source::from_arg(&options.image))?;
+    println!("Size: {}", bytes.len());
+    let rom = file_io::load_bytes(&bytes)?;
+    if !rom.formats.is_empty() {
+        println!("Format: {} ({} bytes unpacked)", format_names(&rom.formats), rom.image.len());
+    }
+
+    // what the rom database knows comes first, then what the rom's container says, so the commandline overrides both
+    let mut database = Database::bundled()?;
+    for filename in options.databases.iter() {
+        database.load(filename)?;
+    }
+    let mut settings = Vec::new();
+    if let Some(entry) = database.lookup(&rom.image) {
+        println!("Title: {}", entry.title);
+        settings.extend(entry.args());
+    }
+    settings.extend(rom.options.iter().cloned());
+    let options = if settings.is_empty() { options } else { parse_args(settings.into_iter().chain(args))? };
+    let base = file_io::place(&rom, options.platform, ENTRY as usize)?;
+    let image = rom.image;
 
-    // load memory
-    mem.load(image, ENTRY as usize).expect("Failed to load image");
-    println!("Memory Dump: {:02x?}", mem.mem);
+    let boot = match &options.boot {
+        Some(filename) => Some(file_io::read(Source::from_arg(filename))?),
+        None => None,
+    };
+
+    // a movie dictates the machine it was recorded on
+    let player = match &options.play {
+        Some(filename) => {
+            let player = MoviePlayer::open(filename)?;
+            player.header.verify(&image, base, boot.as_deref())?;
+            Some(player)
+        },
+        None => None,
+    };
+
+    let header = match &player {
+        Some(player) => player.header.clone(),
+        None => MovieHeader {
+            rom_hash: MovieHeader::rom_hash(&image),
+            boot_hash: boot.as_deref().map(MovieHeader::rom_hash),
+            base,
+            shift_lsb: options.shift_lsb.unwrap_or_else(|| options.platform.shift_lsb()),
+            stack_depth: options.stack_depth.unwrap_or_else(|| options.platform.stack_depth()),
+            protect_font: options.protect_font,
+            rng: options.rng.clone(),
+            seed: options.seed.unwrap_or_else(random_seed),
+            ipf: options.ipf,
+            timing: options.timing,
+            machine_code_policy: options.machine_code_policy,
+            unknown_policy: options.unknown_policy,
+            hash_interval: options.hash_interval
+        },
+    };
+
+    let mut machine = init(&header)?;
+
+    // load memory, memory images bring their own address
+    machine.mem.load(image, header.base)?;
+    if let Some(boot) = boot {
+        machine.boot(boot)?;
+    }
+    println!("Memory Dump: {:02x?}", machine.mem.mem);
 
     // PC
-    println!("EIP: {:#02x}", reg.eip);
+    println!("EIP: {:#02x}", machine.reg.eip);
+
+    let mut movie = match (player, &options.record) {
+        (Some(player), _) => Movie::Play(player),
+        (None, Some(filename)) => Movie::Record(MovieRecorder::create(filename, &header)?),
+        (None, None) => Movie::Off,
+    };
+
+    let mut capture = match &options.capture {
+        Some(filename) => Some(Capture::create(filename, options.scale, options.palette, options.filter)?),
+        None => None,
+    };
+
+    let mut phosphor = Phosphor::new(options.phosphor, &machine.framebuffer);
+
+    if options.headless && options.frames.is_none() && !matches!(movie, Movie::Play(_)) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Headless mode needs --frames or --play").into());
+    }
+
+    // a movie's frames only replay with the instructions per frame it was recorded with
+    let mut speed = Speed::new(header.ipf, matches!(movie, Movie::Off));
+    if let Some(rate) = &options.speed {
+        speed.set_rate(rate)?;
+    }
+
+    // dropped before the summary is printed, a terminal is restored by then
+    let result = {
+        let mut frontend = open_frontend(&options, phosphor.shades())?;
+        let result = run(&mut machine, &mut movie, &mut capture, &mut phosphor, frontend.as_mut(), &mut speed, &options);
+        result.and(frontend.finish().map_err(Chip8Error::from))
+    };
+
+    // a fault stops the machine within a frame, the movie keeps that frame so playback runs into the same fault
+    let frames = match &result {
+        Err(err) if err.is_fault() => machine.frame + 1,
+        _ => machine.frame,
+    };
+    movie.finish(frames)?;
+    if let Some(capture) = capture {
+        capture.finish()?;
+    }
+    result?;
+    if let Some((filename, format)) = &options.dump {
+        let (from, to) = options.dump_range;
+        hexfile::write(*format, filename, from, &machine.mem.mem[from..=to])?;
+        println!("Memory image: {} {:#05x}-{:#05x}", filename, from, to);
+    }
+    println!("Frames: {} State: {:016x}", machine.frame, machine.state_hash());
 
-    execute(0x0000, opcodes, mem, reg);
+    Ok(())
 }
 
 /**
- *  @func   init()     Initialize Memory, registers, opcode handler
+ *  @func   info()      print what is known about a rom without running it
+ *
+ *  @param  args        arguments after "info"
  */
-fn init() -> (Memory, Registers, Operations){
-    (Memory::new(), Registers::new(ENTRY), Operations::new(SHIFTLSB, SPRITEENTRY))
+fn info<I: Iterator<Item = String>>(mut args: I) -> Result<(), Chip8Error> {
+    let usage = || io::Error::new(io::ErrorKind::InvalidInput, USAGE);
+
+    let mut filename = None;
+    let mut database = Database::bundled()?;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--database"                => database.load(&args.next().ok_or_else(usage)?)?,
+            _ if arg.starts_with("--")  => return Err(usage().into()),
+            _                           => filename = Some(arg),
+        }
+    }
+
+    let filename = filename.ok_or_else(usage)?;
+    let rom = file_io::load(Source::from_arg(&filename))?;
+    let image = rom.image;
+    let analysis = analyze(&image, rom.base.unwrap_or(ENTRY as usize) as u16, ENTRY);
+
+    println!("File: {}", filename);
+    if !rom.formats.is_empty() {
+        println!("Format: {}", format_names(&rom.formats));
+    }
+    if let Some(base) = rom.base {
+        println!("Address: {:#05x}", base);
+    }
+    if !rom.options.is_empty() {
+        println!("Options: {}", rom.options.join(" "));
+    }
+    println!("Size: {}", image.len());
+    println!("SHA-1: {}", database::sha1(&image));
+    println!("Title: {}", database.lookup(&image).map_or("unknown", |entry| entry.title.as_str()));
+
+    // the extended instructions the guess is based on, e.g. "schip (00FF at 0x202 needs schip)"
+    let evidence: Vec<String> = analysis.extensions.iter()
+        .map(|(addr, name, platform)| format!("{} at {:#05x} needs {}", name, addr, platform.name()))
+        .collect();
+    if evidence.is_empty() {
+        println!("Platform: {}", analysis.platform.name());
+    } else {
+        println!("Platform: {} ({})", analysis.platform.name(), evidence.join(", "));
+    }
+
+    // most used first
+    let mut histogram: Vec<(&str, u64)> = analysis.histogram.iter().map(|(name, count)| (*name, *count)).collect();
+    histogram.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    println!("Opcodes: {} reachable instructions", histogram.iter().map(|(_, count)| count).sum::<u64>());
+    for (name, count) in histogram {
+        println!("    {}  {}", name, count);
+    }
+
+    for warning in analysis.warnings.iter() {
+        println!("Warning: {}", warning);
+    }
+    Ok(())
 }
 
 /**
- *  @func   run()      Run the Emulator -> execute loop and timer
+ *  @func   format_names()  containers a rom was unpacked from, outermost first, e.g. "zip, hex text"
  */
-fn run() {
-    Display::run();
+fn format_names(formats: &[container::Format]) -> String {
+    formats.iter().map(|format| format.name()).collect::<Vec<_>>().join(", ")
 }
 
 /**
-*  @func   execute()   execute instruction
-*
-*  @param  opc         opcode of instruction
-* */
-fn execute(opc: u16, opcodes: Operations, pmem: Memory, preg: Registers) -> Result<(), io::Error>{
-    let MSN: u8 = ((0xF000 & opc) >> 12) as u8;
-
-    match MSN {
-        0x0 => {
-            match opc {
-                0x00E0 => {
-                    opcodes.clear_display();
-                    Ok(())
-                },
-                0x00EE => {
-                    opcodes.return_from_call(pmem, preg);
-                    Ok(())
-                },
-                _ => Err(io::Error::new(io::ErrorKind::Other, "Unknown opcode")),
-            }
-        },
-        0x1 => {
-            opcodes.jmp_address(opc, preg);
-            Ok(())
-        },
-        0x2 => {
-            opcodes.call_subroutine(opc, pmem, preg);
-            Ok(())
-        },
-        0x3 => {
-            opcodes.reg_val_compare(opc, preg);
-            Ok(())
-        },
-        0x4 => {
-            opcodes.reg_val_noncompare(opc, preg);
-            Ok(())
-        },
-        0x5 => {
-            opcodes.reg_compare(opc, preg);
-            Ok(())
-        },
-        0x6 => {
-            opcodes.reg_set(opc, preg);
-            Ok(())
-        },
-        0x7 => {
-            opcodes.reg_add(opc, preg);
-            Ok(())
+ *  @func   init()      Initialize Memory, registers, opcode handler
+ *
+ *  @param  header      quirks, seed and policies the machine runs with
+ */
+fn init(header: &MovieHeader) -> Result<Machine, Chip8Error> {
+    let rng = Rng::new(&header.rng, header.seed)?;
+    let opcodes = Operations::new(header.shift_lsb, SPRITEENTRY, header.machine_code_policy, header.unknown_policy);
+    let mut machine = Machine::new(opcodes, ENTRY, rng)?;
+
+    machine.mem.stack_depth = header.stack_depth;
+    machine.mem.protect_font = header.protect_font;
+    machine.timing = header.timing;
+    if header.machine_code_policy == Policy::Trap || header.unknown_policy == Policy::Trap {
+        machine.trap = Some(Box::new(trace_trap));
+    }
+    Ok(machine)
+}
+
+/**
+ *  @func   trace_trap()    trap handler of the commandline, prints the registers and continues
+ */
+fn trace_trap(opc: u16, _pmem: &mut Memory, preg: &mut Registers) -> Result<(), Chip8Error> {
+    eprintln!("Trap: {:04X} at PC {:#05x} V: {:02x?} I: {:#05x}", opc, preg.eip, preg.register_array, preg.address_register);
+    Ok(())
+}
+
+/**
+ *  @func   open_frontend() frontend chosen on the commandline
+ *
+ *  @param  shades          shades shown before the first frame
+ */
+fn open_frontend(options: &Options, shades: &Shades) -> Result<Box<dyn Frontend>, io::Error> {
+    if options.headless {
+        return match &options.export {
+            Some(filename) => Ok(Box::new(Export::create(filename, options.scale, options.palette, options.filter)?)),
+            None => Ok(Box::new(Headless)),
+        };
+    }
+
+    match options.terminal {
+        Some(cells) => Ok(Box::new(Terminal::open(cells, options.palette, options.keys)?)),
+        None => {
+            let screen = Screen {
+                title: options.title.clone().unwrap_or_else(|| "Canvas".to_string()),
+                scale: options.scale,
+                palette: options.palette,
+                viewport: options.viewport,
+                filter: options.filter,
+                keys: options.keys
+            };
+            Ok(Box::new(Display::open(screen, shades)?))
         },
-        0x8 => {
-            // least significant nibble
-            let LSN: u8  = (opc & 0xF) as u8;
-            
-            match LSN {
-                0x0 => {
-                    opcodes.reg_assign(opc, preg);
-                    Ok(())
-                },
-                0x1 => {
-                    opcodes.reg_or(opc, preg);
-                    Ok(())
-                },
-
-                0x2 => {
-                    opcodes.reg_and(opc, preg);
-                    Ok(())
-                },
-
-                0x3 => {
-                    opcodes.reg_xor(opc, preg);
-                    Ok(())
-                },
-
-                0x4 => {
-                    opcodes.reg_add(opc, preg);
-                    Ok(())
-                },
-
-                0x5 => {
-                    opcodes.regx_sub_regy(opc, preg);
-                    Ok(())
-                },
-
-                0x6 => {
-                    opcodes.lsb_shift_right(opc, preg);
-                    Ok(())
-                },
-
-                0x7 => {
-                    opcodes.regy_sub_regx(opc, preg);
-                    Ok(())
-                },
-
-                0xE => {
-                    opcodes.lsb_shift_left(opc, preg);
-                    Ok(())
-                },
-                _ => Err(io::Error::new(io::ErrorKind::Other, "Unknown opcode")),
+    }
+}
+
+/**
+ *  @func   run()       run the machine and present the frames to the frontend
+ *
+ *  @param  frontend    paces the host frames, provides the keypad and shows the frames
+ *
+ *  @param  speed       emulated frames per host frame and instructions per frame
+ *
+ *  @param  options     frames to run (a played movie stops at its end) and screenshots to take
+ */
+fn run(machine: &mut Machine, movie: &mut Movie, capture: &mut Option<Capture>, phosphor: &mut Phosphor, frontend: &mut dyn Frontend, speed: &mut Speed, options: &Options) -> Result<(), Chip8Error> {
+    let mut keypad = Keypad::new();
+    let done = |machine: &Machine, movie: &Movie| movie.done(machine.frame) || options.frames.is_some_and(|n| machine.frame >= n);
+
+    let mut timer = Timer::new(RATE);
+
+    take_screenshots(machine.frame, phosphor.shades(), options)?;
+    while !done(machine, movie) {
+        // a host that fell behind runs the emulated frames of all host frames due, but polls and draws once
+        let due = if frontend.paced() { timer.wait() } else { 1 };
+
+        if !frontend.poll(&mut keypad, speed, machine.frame)? {
+            break;
+        }
+
+        let frames = (0..due).fold(Frames::Count(0), |frames, _| match (frames, speed.frames()) {
+            (Frames::Count(ran), Frames::Count(n)) => Frames::Count(ran + n),
+            _ => Frames::Uncapped,
+        });
+        let mut ran = 0;
+
+        while !done(machine, movie) {
+            match frames {
+                Frames::Count(n) if ran >= n => break,
+                Frames::Uncapped if ran > 0 && Instant::now() >= timer.next() => break,
+                _ => {},
             }
-        },
-        0x9 => {
-            opcodes.reg_noncompare(opc, preg);
-            Ok(())
-        },
-        0xA => {
-            opcodes.jmp_I(opc, preg);
-            Ok(())
-        },
-        0xB => {
-            opcodes.jmp_offset(opc, preg);
-            Ok(())
-        },
-        0xC => {
-            opcodes.rand_reg(opc, preg);
-            Ok(())
-        },
-        0xD => {
-            opcodes.draw_sprite(opc, preg);
-            Ok(())
-        },
-        0xE => {
-            let DLSN: u8 = (opc & 0xFF) as u8;
-            match DLSN {
-                0x9E => {
-                    opcodes.stored_key_pressed(opc, preg);
-                    Ok(())
-                },
-
-                0xA1 => {
-                    opcodes.stored_key_notpressed(opc);
-                    Ok(())
-                },
-                _ => Err(io::Error::new(io::ErrorKind::Other, "Unknown opcode")),
-            } 
-        },
-        0xF => {
-            let DLSN: u8 = (opc & 0xFF) as u8;
-
-            match DLSN {
-                0x07 => {
-                    Ok(())
-                },
-                0x0A => {
-                    Ok(())
-                },
-                0x15 => {
-                    Ok(())
-                },
-                0x18 => {
-                    Ok(())
-                },
-                0x1E => {
-                    Ok(())
-                },
-                0x29 => {
-                    Ok(())
-                },
-                0x33 => {
-                    Ok(())
-                },
-                0x55 => {
-                    Ok(())
-                },
-                0x65 => {
-                    Ok(())
-                },
-                _ => Err(io::Error::new(io::ErrorKind::Other, "Unknown opcode")),
+
+            movie.before_frame(machine, keypad)?;
+            machine.run_frame(speed.ipf)?;
+            movie.after_frame(machine)?;
+
+            phosphor.update(&machine.framebuffer);
+            if let Some(capture) = capture {
+                capture.frame(phosphor.shades());
             }
-        },
-        _   => Err(io::Error::new(io::ErrorKind::Other, "Unknown opcode")),
+            frontend.sound(machine.sound());
+            frontend.present(phosphor.shades(), machine.frame)?;
+            take_screenshots(machine.frame, phosphor.shades(), options)?;
+            ran += 1;
+        }
+    }
+
+    Ok(())
+}
+
+/**
+ *  @func   take_screenshots()  write the screenshots requested for the frame that just ran
+ */
+fn take_screenshots(frame: u64, shades: &Shades, options: &Options) -> Result<(), io::Error> {
+    for (at, filename) in options.screenshots.iter() {
+        if *at == frame {
+            Image::from_shades(shades, options.scale, &options.palette, options.filter).write_png(filename)?;
+        }
     }
+    Ok(())
+}
+
+/**
+ *  @func   parse_args()    parse the commandline
+ *
+ *  @param  args            arguments without the program name
+ */
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Options, io::Error> {
+    let mut options = Options {
+        image: IMAGE.to_string(),
+        headless: false,
+        terminal: None,
+        frames: None,
+        screenshots: Vec::new(),
+        scale: SCALE,
+        palette: Palette::default(),
+        phosphor: Blend::Off,
+        viewport: VIEWPORT,
+        filter: Filter::Nearest,
+        export: None,
+        record: None,
+        capture: None,
+        play: None,
+        hash_interval: HASH_INTERVAL,
+        seed: None,
+        rng: RNG.to_string(),
+        ipf: IPF,
+        speed: None,
+        title: None,
+        keys: KeyMap::default(),
+        databases: Vec::new(),
+        timing: TIMING,
+        protect_font: false,
+        shift_lsb: None,
+        platform: PLATFORM,
+        stack_depth: None,
+        machine_code_policy: MACHINE_CODE_POLICY,
+        unknown_policy: UNKNOWN_POLICY,
+        boot: None,
+        dump: None,
+        dump_range: (0, MEMSIZE - 1)
+    };
+
+    let usage = || io::Error::new(io::ErrorKind::InvalidInput, USAGE);
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--headless"        => options.headless = true,
+            "--terminal"        => options.terminal = Some(Cells::from_name(&args.next().ok_or_else(usage)?)?),
+            "--frames"          => options.frames = Some(parse_number(args.next())?),
+            "--screenshot-at-frame" => {
+                let frame = parse_number(args.next())?;
+                options.screenshots.push((frame, args.next().ok_or_else(usage)?));
+            },
+            "--scale"           => options.scale = parse_number(args.next())? as usize,
+            "--theme"           => options.palette = Palette::from_name(&args.next().ok_or_else(usage)?)?,
+            "--colors"          => options.palette.set_colors(&args.next().ok_or_else(usage)?)?,
+            "--phosphor"        => options.phosphor = Blend::from_name(&args.next().ok_or_else(usage)?)?,
+            "--viewport"        => options.viewport = Viewport::from_name(&args.next().ok_or_else(usage)?)?,
+            "--filter"          => options.filter = Filter::from_name(&args.next().ok_or_else(usage)?)?,
+            "--export"          => options.export = Some(args.next().ok_or_else(usage)?),
+            "--record"          => {
+                // a gif or png records the screen, anything else the keypad
+                let filename = args.next().ok_or_else(usage)?;
+                if Capture::is_capture(&filename) {
+                    options.capture = Some(filename);
+                } else {
+                    options.record = Some(filename);
+                }
+            },
+            "--play"            => options.play = Some(args.next().ok_or_else(usage)?),
+            "--hash-interval"   => options.hash_interval = parse_number(args.next())?,
+            "--seed"            => options.seed = Some(parse_number(args.next())?),
+            "--rng"             => options.rng = args.next().ok_or_else(usage)?,
+            "--ipf"             => options.ipf = parse_number(args.next())? as u32,
+            "--speed"           => options.speed = Some(args.next().ok_or_else(usage)?),
+            "--title"           => options.title = Some(args.next().ok_or_else(usage)?),
+            "--keys"            => options.keys.set_keys(&args.next().ok_or_else(usage)?)?,
+            "--database"        => options.databases.push(args.next().ok_or_else(usage)?),
+            "--timing"          => options.timing = Timing::from_name(&args.next().ok_or_else(usage)?)?,
+            "--protect-font"    => options.protect_font = true,
+            "--shift-quirk"     => options.shift_lsb = Some(false),
+            "--platform"        => options.platform = Platform::from_name(&args.next().ok_or_else(usage)?)?,
+            "--stack-depth"     => options.stack_depth = Some(parse_number(args.next())? as usize),
+            "--on-machine-code" => options.machine_code_policy = Policy::from_name(&args.next().ok_or_else(usage)?)?,
+            "--on-unknown"      => options.unknown_policy = Policy::from_name(&args.next().ok_or_else(usage)?)?,
+            "--boot"            => options.boot = Some(args.next().ok_or_else(usage)?),
+            "--dump"            => {
+                let filename = args.next().ok_or_else(usage)?;
+                options.dump = Some((filename.clone(), HexFormat::from_filename(&filename)?));
+            },
+            "--dump-range"      => options.dump_range = parse_range(args.next())?,
+            _ if arg.starts_with("--") => return Err(usage()),
+            _                   => options.image = arg,
+        }
+    }
+
+    if options.record.is_some() && options.play.is_some() || options.scale == 0 {
+        return Err(usage());
+    }
+
+    // every output is upscaled the same way
+    options.scale = options.filter.scale(options.scale);
+
+    // exports are frame exact, only headless runs aren't paced by the host
+    if options.export.is_some() && !options.headless {
+        return Err(usage());
+    }
+
+    // native only makes sense for 0NNN, and a booted interpreter owns its memory
+    if options.unknown_policy == Policy::Native || (options.boot.is_some() && options.protect_font) {
+        return Err(usage());
+    }
+
+    Ok(options)
+}
+
+/**
+ *  @func   expand_config()     replace --config file by the options in the file
+ *
+ *  @param  args                arguments without the program name
+ *
+ *  Every line of the file is an option without its leading dashes, followed by its values,
+ *  e.g. "theme amber". Empty lines and lines starting with # are skipped. Options given
+ *  after --config on the commandline override the file.
+ */
+fn expand_config<I: Iterator<Item = String>>(mut args: I) -> Result<Vec<String>, io::Error> {
+    let mut expanded = Vec::new();
+
+    while let Some(arg) = args.next() {
+        if arg != "--config" {
+            expanded.push(arg);
+            continue;
+        }
+
+        let filename = args.next().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, USAGE))?;
+        for line in fs::read_to_string(filename)?.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut words = line.split_whitespace();
+            expanded.extend(words.next().map(|option| format!("--{}", option)));
+            expanded.extend(words.map(str::to_string));
+        }
+    }
+    Ok(expanded)
+}
+
+fn parse_number(arg: Option<String>) -> Result<u64, io::Error> {
+    arg.and_then(|value| value.parse::<u64>().ok())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, USAGE))
+}
+
+/**
+ *  @func   parse_range()   inclusive range of memory addresses in hex, e.g. "200-2ff"
+ */
+fn parse_range(arg: Option<String>) -> Result<(usize, usize), io::Error> {
+    let usage = || io::Error::new(io::ErrorKind::InvalidInput, USAGE);
+    let arg = arg.ok_or_else(usage)?;
+    let (from, to) = arg.split_once('-').ok_or_else(usage)?;
+
+    let address = |value: &str| usize::from_str_radix(value.trim_start_matches("0x"), 16).map_err(|_| usage());
+    let (from, to) = (address(from)?, address(to)?);
+    if from > to || to >= MEMSIZE {
+        return Err(usage());
+    }
+    Ok((from, to))
+}
+
+/**
+ *  @func   random_seed()   seed used when none is given on the commandline
+ */
+fn random_seed() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|t| t.as_nanos() as u64).unwrap_or(0)
 }